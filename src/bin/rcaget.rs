@@ -1,21 +1,41 @@
-use epics_tools::{wait_connect, get_channels, grab_info};
-use std::ffi::CStr;
-use std::time::Duration;
+use epics_tools::{
+    add_prefix, build_context, connect_with_retry, install_ctrl_c, get_channels, read_pv_list, report_failed, grab_info_as,
+    grab_full_info_as, grab_precision, log, parse_pv_spec, read_with_timeout, set_verbosity, wait_connect,
+};
+use std::process::ExitCode;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::ffi::{CStr, OsString};
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
 
-use clap::{arg, Command};
+use clap::{arg, Arg, Command};
 use epics_ca::{
-    types::Value,
+    types::{FieldId, Value},
     Channel, Context,
 };
 use epics_tools::{
-    config::{DEFAULT_WAIT_TIME, wait_time_in_range},
-    types::Info,
+    config::{
+        ca_env::{self, CaEnv},
+        DEFAULT_CSV_SEPARATOR, DEFAULT_MAX_CONCURRENT, DEFAULT_PLACEHOLDER, DEFAULT_NAME_WIDTH, DEFAULT_PRECISION, DEFAULT_RETRIES, DEFAULT_SEPARATOR, DEFAULT_STAMP_DIGITS, DEFAULT_TIMESTAMP,
+        DEFAULT_WAIT_TIME,
+        FloatFormat, IndexRange, TimestampKind,
+        count_in_range, dbr_type, index_range, name_pattern, stamp_digits, timestamp_kind, validate_pv_name,
+        wait_time_in_range
+    },
+    types::{json_string, wrap_elements, FormatOpts, Info, Radix, SystemClock, TimestampFormatter},
+    FailedChannels,
     UnifiedError,
     UnifiedResult
 };
 
-use futures::TryFutureExt;
-use tokio::{select, task::JoinSet, time::sleep};
+use futures::future::{join_all, pending, Either};
+use regex::Regex;
+use tokio::{select, sync::Semaphore, task::JoinSet, time::{sleep, timeout}};
+
+// Clears the terminal and moves the cursor to the top left corner, like watch(1)
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -23,42 +43,307 @@ const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 
 struct Config {
     names: Vec<String>,
+    // Per-PV connection timeouts given as PV@<sec>, overriding -w
+    timeouts: HashMap<String, f32>,
     wait_time: f32,
+    read_timeout: f32,
+    // Bounds the whole batch, connections and reads included
+    deadline: Option<f32>,
+    // Read everything again every so many seconds, until Ctrl-C
+    poll: Option<f32>,
+    clear: bool,
+    retries: u32,
+    max_concurrent: usize,
+    dbr: Option<FieldId>,
+    timestamp: TimestampKind,
+    // Maximum number of array elements to print
+    count: Option<usize>,
+    // Print only these array elements
+    slice: Option<IndexRange>,
+    // Array elements per line
+    wrap: Option<usize>,
+    // Each array element on a line of its own, as name[i]
+    one_per_line: bool,
+    // Append min/max/mean/count to numeric arrays or, with stats_only, print them instead
+    stats: bool,
+    stats_only: bool,
+    // Dump char arrays like hexdump -C, on the lines after the PV
+    hexdump: bool,
+    format: FormatOpts,
     // Flags
+    /*
+       Read the channels concurrently. Unlike caget, this doesn't change the kind of get: epics-ca
+       only wraps ca_array_get_callback, so every read (with or without -c) already waits for the
+       server's reply to that request, rather than for a ca_pend_io flush of a plain ca_array_get.
+    */
     asynchronous: bool,
     terse: bool,
+    show_name: bool,
+    name_width: usize,
     wide: bool,
+    json: bool,
+    csv: bool,
+    units: bool,
+    limits: bool,
+    show_type: bool,
+    // Print the element count for scalars too, so that every line has the same columns
+    always_count: bool,
+    // Blank line between PVs
+    group: bool,
+    // PV name on a line of its own, before the value
+    header: bool,
+    // Printed instead of the value for PVs that couldn't be read
+    placeholder: Option<String>,
+    raw: bool,
+    quiet_missing: bool,
+    // Only check that the names are acceptable, don't connect
+    validate: bool,
+    // Write the results to this file, instead of stdout
+    output: Option<String>,
+    append: bool,
+}
+
+impl Config {
+    // Whether we need to ask for the Ctrl metadata, on top of the value
+    fn needs_ctrl(&self) -> bool {
+        self.units || self.limits
+    }
+
+    // Whether the record's precision is going to be used. The raw and JSON outputs don't format
+    fn needs_precision(&self) -> bool {
+        self.format.record_precision && !self.raw && !self.json
+    }
 }
 
 async fn get_arguments() -> UnifiedResult<Config> {
+    parse_arguments(std::env::args_os())
+}
+
+fn parse_arguments<I, T>(args: I) -> UnifiedResult<Config>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
     let matches = Command::new(PKG_NAME)
         .version(PKG_VERSION)
         .author(PKG_AUTHORS)
         .about("Rust caget")
         .args([
-            arg!(wait: -w <sec> "-w <sec>: Wait time, specifies CA timeout")
+            arg!(wait: -w --"connection-timeout" <sec> "-w <sec>: Wait time, specifies CA timeout")
                 .default_value(DEFAULT_WAIT_TIME)
                 .value_parser(wait_time_in_range),
-            arg!(asget: -c "Asynchronous get (use a callback and wait for completion)"),
+            arg!(addr_list: --"addr-list" <addrs> "Override EPICS_CA_ADDR_LIST"),
+            arg!(auto_addr: --"auto-addr" <yes_no> "Override EPICS_CA_AUTO_ADDR_LIST")
+                .value_parser(ca_env::yes_no),
+            arg!(deadline: --deadline <sec> "Give up on the PVs not read after <sec> seconds in total")
+                .value_parser(wait_time_in_range),
+            arg!(poll: --poll <sec> "Read and print the PVs again every <sec> seconds, until Ctrl-C")
+                .value_parser(wait_time_in_range)
+                .conflicts_with("deadline"),
+            arg!(clear: --clear "With --poll, clear the screen before each round").requires("poll"),
+            arg!(read_timeout: --"read-timeout" <sec> "Timeout for reading the values (default: -w)")
+                .value_parser(wait_time_in_range),
+            arg!(retries: -r <n> "-r <n>: Retry connecting up to <n> times before giving up on a PV")
+                .default_value(DEFAULT_RETRIES)
+                .value_parser(clap::value_parser!(u32)),
+            arg!(dbr: -d <type> "-d <type>: Request this type (e.g. DBR_STRING) instead of the native one")
+                .value_parser(dbr_type),
+            arg!(max_concurrent: --"max-concurrent" <n> "Maximum number of PVs read at once with -c")
+                .default_value(DEFAULT_MAX_CONCURRENT)
+                .value_parser(count_in_range),
+            arg!(asget: -c "Asynchronous get: read the PVs concurrently (every get already uses a callback)"),
             arg!(terse: -t "Terse mode - print only value, without name"),
+            arg!(no_name: --"no-name" "Don't print the PV names, keeping the rest of the layout"),
+            arg!(name_width: --"name-width" <n> "Pad the PV names to <n> characters")
+                .default_value(DEFAULT_NAME_WIDTH)
+                .value_parser(clap::value_parser!(usize)),
             arg!(wide: -a "Wide mode \"name timestamp value stat sevr\""),
-            arg!(names: <PV> ... "PV names"),
+            arg!(numeric: -n "Print DBF_ENUM values as numbers"),
+            arg!(units: -u "Print the engineering units after the value"),
+            arg!(limits: --limits "Print the display, control and alarm limits after the value"),
+            arg!(show_type: --"show-type" "Print the native DBF type of the channel after its name"),
+            arg!(always_count: --"always-count" "Print the element count for scalars too (as 1)"),
+            arg!(group: --group "Print a blank line between PVs").conflicts_with("terse"),
+            arg!(header: --header "Print the PV name on its own line, before the value")
+                .conflicts_with("terse"),
+            arg!(quiet_missing: --"quiet-missing" "Don't report PVs that fail to connect, just exit with an error"),
+            arg!(output: --output <file> "Write the results to <file> instead of stdout"),
+            arg!(append: --append "With --output, append to the file instead of truncating it")
+                .requires("output"),
+            arg!(validate: --validate "Check the PV names and exit, without connecting to them"),
+            arg!(raw: --raw "Print the received data structures, unformatted, for debugging"),
+            arg!(json: -j --json "Print the values as JSON objects").conflicts_with("csv"),
+            arg!(csv: --csv "Print one \"name,timestamp,value\" row per PV"),
+            arg!(separator: -F <sep> "-F <sep>: Use <sep> as the output field separator"),
+            arg!(human: --human "Print numbers with SI prefixes, like 1.50M"),
+            arg!(stats: --stats "Print min, max, mean and count after the values of numeric arrays"),
+            arg!(stats_only: --"stats-only" "Print the statistics of numeric arrays instead of their values"),
+            arg!(hexdump: --hexdump "Print DBF_CHAR arrays as a hex dump, with offsets and ASCII")
+                .conflicts_with("char_string"),
+            arg!(align: --align "Right-align array elements in columns of equal width"),
+            arg!(char_string: -S "Print DBF_CHAR arrays as strings, and scalars as characters"),
+            arg!(hex: -x "Print integer values in hexadecimal").conflicts_with("octal"),
+            arg!(octal: -o "Print integer values in octal"),
+            arg!(precision: -p <digits>
+                 "-p <digits>: Precision for floating point values (default: the record's PREC)")
+                .value_parser(clap::value_parser!(usize)),
+            arg!(sci: -e "Use scientific notation for floating point values")
+                .conflicts_with_all(["fixed", "shortest"]),
+            arg!(fixed: -f "Use fixed point notation for floating point values (default)")
+                .conflicts_with("shortest"),
+            arg!(shortest: -g "Use the shortest notation for floating point values"),
+            arg!(trim_zeros: --"trim-zeros" "Drop the trailing zeros of floating point values, like 3.14 for 3.14000"),
+            arg!(timestamp: --timestamp <kind>
+                 "Wide mode timestamp: (s)erver, (c)lient, (i)ncremental, (I)ncremental by channel, (r)elative, (n)one")
+                .value_parser(timestamp_kind),
+            arg!(stamp_digits: --"stamp-digits" <digits> "Fractional digits for timestamps: 0, 3, 6 or 9")
+                .value_parser(stamp_digits),
+            arg!(utc: --utc "Print timestamps in UTC, instead of local time"),
+            Arg::new("count")
+                .short('#')
+                .long("count")
+                .value_name("n")
+                .help("-# <n>: Print at most <n> array elements")
+                .value_parser(count_in_range),
+            Arg::new("default")
+                .long("default")
+                .value_name("str")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value(DEFAULT_PLACEHOLDER)
+                .help("Print <str> (N/A if not given) as the value of PVs that can't be read"),
+            arg!(slice: --slice <range>
+                 "Print only the array elements [start, end). Negative indices count from the end")
+                .allow_hyphen_values(true)
+                .value_parser(index_range)
+                .conflicts_with("count"),
+            arg!(wrap: --wrap <n> "Print arrays <n> elements per line, indenting the continuation lines")
+                .value_parser(count_in_range),
+            arg!(one_per_line: --"one-per-line" "Print each array element on its own line, as \"name[i] value\"")
+                .conflicts_with_all(["wrap", "hexdump", "stats", "stats_only"]),
+            arg!(verbose: -v ... "Print diagnostics to stderr (repeat for more detail)"),
+            arg!(list: -l <file> "-l <file>: Read PV names from a file, one per line (- for stdin)"),
+            arg!(name_match: --match <regex> "Keep only the PV names that match <regex>, anywhere in the name")
+                .value_parser(name_pattern),
+            arg!(prefix: --prefix <str> "Prepend <str> to every PV name, including those read with -l"),
+            arg!(names: [PV] ... "PV names, optionally with their own timeout as PV@<sec>"),
         ])
-        .get_matches();
+        .get_matches_from(args);
+
+    set_verbosity(matches.get_count("verbose"));
+    ca_env::apply_overrides(
+        matches.get_one::<String>("addr_list"),
+        matches.get_one::<bool>("auto_addr").copied(),
+    );
 
-    let names = matches
+    let mut names: Vec<String> = matches
         .get_many::<String>("names")
-        .unwrap()
-        .cloned()
+        .map(|names| names.cloned().collect())
+        .unwrap_or_default();
+    if let Some(path) = matches.get_one::<String>("list") {
+        names.extend(read_pv_list(path)?);
+    }
+    if let Some(prefix) = matches.get_one::<String>("prefix") {
+        add_prefix(&mut names, prefix);
+    }
+    if names.is_empty() {
+        return Err(UnifiedError::Misc("No PV names given".into()));
+    }
+    let mut timeouts = HashMap::new();
+    let mut names: Vec<String> = names
+        .iter()
+        .map(|spec| {
+            let (name, timeout) = parse_pv_spec(spec);
+            if let Some(secs) = timeout {
+                timeouts.insert(name.clone(), secs);
+            }
+            name
+        })
         .collect();
+    if let Some(pattern) = matches.get_one::<Regex>("name_match") {
+        names.retain(|name| pattern.is_match(name));
+        if names.is_empty() {
+            return Err(UnifiedError::Misc(format!("No PV names match '{pattern}'")));
+        }
+    }
     let wait_time = *matches.get_one::<f32>("wait").unwrap();
 
     Ok(Config {
         names,
+        timeouts,
         wait_time,
+        read_timeout: matches.get_one::<f32>("read_timeout").copied().unwrap_or(wait_time),
+        deadline: matches.get_one::<f32>("deadline").copied(),
+        poll: matches.get_one::<f32>("poll").copied(),
+        clear: matches.get_flag("clear"),
+        retries: *matches.get_one::<u32>("retries").unwrap(),
+        max_concurrent: *matches.get_one::<usize>("max_concurrent").unwrap(),
+        dbr: matches.get_one::<FieldId>("dbr").copied(),
+        timestamp: matches
+            .get_one::<TimestampKind>("timestamp")
+            .copied()
+            .unwrap_or(DEFAULT_TIMESTAMP),
+        count: matches.get_one::<usize>("count").copied(),
+        slice: matches.get_one::<IndexRange>("slice").copied(),
+        wrap: matches.get_one::<usize>("wrap").copied(),
+        one_per_line: matches.get_flag("one_per_line"),
+        stats: matches.get_flag("stats") || matches.get_flag("stats_only"),
+        stats_only: matches.get_flag("stats_only"),
+        hexdump: matches.get_flag("hexdump"),
         asynchronous: matches.get_flag("asget"),
         terse: matches.get_flag("terse"),
+        show_name: !matches.get_flag("no_name"),
+        name_width: *matches.get_one::<usize>("name_width").unwrap(),
         wide: matches.get_flag("wide"),
+        json: matches.get_flag("json"),
+        csv: matches.get_flag("csv"),
+        units: matches.get_flag("units"),
+        limits: matches.get_flag("limits"),
+        show_type: matches.get_flag("show_type"),
+        always_count: matches.get_flag("always_count"),
+        group: matches.get_flag("group"),
+        header: matches.get_flag("header"),
+        placeholder: matches.get_one::<String>("default").cloned(),
+        raw: matches.get_flag("raw"),
+        quiet_missing: matches.get_flag("quiet_missing"),
+        validate: matches.get_flag("validate"),
+        output: matches.get_one::<String>("output").cloned(),
+        append: matches.get_flag("append"),
+        format: FormatOpts {
+            radix: if matches.get_flag("hex") {
+                Radix::Hex
+            } else if matches.get_flag("octal") {
+                Radix::Octal
+            } else {
+                Radix::Decimal
+            },
+            float: {
+                let prec = matches.get_one::<usize>("precision").copied().unwrap_or(DEFAULT_PRECISION);
+                if matches.get_flag("sci") {
+                    FloatFormat::Scientific(prec)
+                } else if matches.get_flag("shortest") {
+                    FloatFormat::Shortest(prec)
+                } else {
+                    FloatFormat::Fixed(prec)
+                }
+            },
+            enum_as_number: matches.get_flag("numeric"),
+            stamp_digits: matches
+                .get_one::<usize>("stamp_digits")
+                .copied()
+                .unwrap_or(DEFAULT_STAMP_DIGITS),
+            utc: matches.get_flag("utc"),
+            char_as_string: matches.get_flag("char_string"),
+            record_precision: !matches.contains_id("precision"),
+            align_columns: matches.get_flag("align"),
+            human: matches.get_flag("human"),
+            trim_zeros: matches.get_flag("trim_zeros"),
+            separator: match matches.get_one::<String>("separator") {
+                Some(sep) => sep.clone(),
+                None if matches.get_flag("csv") => DEFAULT_CSV_SEPARATOR.into(),
+                None => DEFAULT_SEPARATOR.into(),
+            },
+        },
     })
 }
 
@@ -72,96 +357,1110 @@ pub async fn connect<V: Value + ?Sized>(
     Ok(typed.into_value())
 }
 
-fn print_formatted(chan_info: &Info, config: &Config) {
-    let mut components = vec![];
-    let scalar = chan_info.is_scalar();
+/*
+   The [start, end) array elements to print. There's no way to request a sub-range from the
+   server, so the whole array is read and sliced here.
+*/
+fn array_range(chan_info: &Info, config: &Config) -> (usize, usize) {
+    match (config.slice, config.count) {
+        (Some(range), _) => range.resolve(chan_info.valid_elements),
+        (None, Some(count)) => (0, count.min(chan_info.elements)),
+        (None, None) => (0, chan_info.valid_elements),
+    }
+}
+
+/*
+   One implementation per output mode, picked by `formatter`. `format` takes `&mut self` because
+   the incremental timestamps of the plain output remember the previous value.
+*/
+trait OutputFormatter {
+    // The text for a PV, without the trailing line break
+    fn format(&mut self, chan_info: &Info) -> String;
+
+    // Stands for a PV that couldn't be read, with the same columns that `format` would give it
+    fn placeholder(&mut self, name: &str, placeholder: &str) -> String;
+
+    // Printed once, before the PVs
+    fn header(&self) -> Option<String> {
+        None
+    }
+}
+
+fn formatter<'a>(
+    config: &'a Config,
+    stamps: &'a mut TimestampFormatter,
+) -> Box<dyn OutputFormatter + 'a> {
+    if config.raw {
+        Box::new(RawFormatter { config })
+    } else if config.json {
+        Box::new(JsonFormatter { config })
+    } else if config.csv {
+        Box::new(CsvFormatter { config })
+    } else {
+        Box::new(PlainFormatter { config, stamps })
+    }
+}
+
+// The caget-like output, including its terse and wide variants
+struct PlainFormatter<'a> {
+    config: &'a Config,
+    stamps: &'a mut TimestampFormatter,
+}
+
+impl PlainFormatter<'_> {
+    // --one-per-line: the elements in [start, end), each with the columns that a scalar would get
+    fn format_each(&mut self, chan_info: &Info, start: usize, end: usize) -> String {
+        let config = self.config;
+        let stamp = match config.wide {
+            true => self.stamps.format(chan_info, &config.format),
+            false => None,
+        };
+        let units = chan_info.units.as_ref().filter(|u| config.units && !u.is_empty());
 
-    if !config.terse {
-        components.push(if scalar {
-            format!("{:<30}", chan_info.name)
+        chan_info
+            .format_elements_range(start, end, &config.format)
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let mut components = vec![];
+                if config.show_name && !config.terse && !config.header {
+                    let name = format!("{}[{}]", chan_info.name, start + i);
+                    components.push(format!("{name:<width$}", width = config.name_width));
+                }
+                if config.show_type && !config.terse {
+                    components.push(chan_info.field_type.to_string());
+                }
+                components.extend(stamp.clone());
+                components.push(value);
+                components.extend(units.cloned());
+                if config.wide {
+                    components.push(chan_info.format_alarm());
+                }
+                components.join(&config.format.separator)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl OutputFormatter for PlainFormatter<'_> {
+    fn format(&mut self, chan_info: &Info) -> String {
+        let config = self.config;
+        let scalar = chan_info.is_scalar();
+        // Scalars and char strings are a single value already. Empty arrays keep their usual line
+        if config.one_per_line && !scalar && !chan_info.is_char_string(&config.format) {
+            let (start, end) = array_range(chan_info, config);
+            if end > start {
+                return self.format_each(chan_info, start, end);
+            }
+        }
+        let stamps = &mut *self.stamps;
+        let mut components = vec![];
+
+        // Terse mode never shows the name, whatever --no-name says. With --header it's shown already
+        if config.show_name && !config.terse && !config.header {
+            components.push(format!("{:<width$}", chan_info.name, width = config.name_width));
+        }
+        if config.show_type && !config.terse {
+            components.push(chan_info.field_type.to_string());
+        }
+
+        if config.wide {
+            if let Some(stamp) = stamps.format(chan_info, &config.format) {
+                components.push(stamp);
+            }
+        }
+
+        let (start, end) = array_range(chan_info, config);
+        // Over the whole array, whatever part of it gets printed. Char strings aren't numbers
+        let stats = match (scalar || chan_info.is_char_string(&config.format), config.stats) {
+            (false, true) => chan_info.format_stats(&config.format),
+            _ => None,
+        };
+        // The statistics carry their own count
+        let values_shown = !(config.stats_only && stats.is_some());
+        // Like caget -t, terse arrays are just their values
+        if scalar && config.always_count && !config.terse {
+            components.push("1".into());
+        } else if !scalar && !config.terse && !chan_info.is_char_string(&config.format) && values_shown {
+            components.push(format!("{}", end - start));
+        }
+        let dump = match config.hexdump {
+            true => chan_info.value.hexdump(start, end),
+            false => None,
+        };
+        let value = if !values_shown || dump.is_some() {
+            String::new()
+        } else if scalar {
+            chan_info.format_scalar(&config.format)
+        } else if let (Some(per_line), false) = (config.wrap, chan_info.is_char_string(&config.format)) {
+            // Continuation lines start under the first element
+            let prefix: usize = components
+                .iter()
+                .map(|c| c.chars().count() + config.format.separator.chars().count())
+                .sum();
+            let indent = format!("\n{}", " ".repeat(prefix));
+            let elements = chan_info.format_elements_range(start, end, &config.format);
+            wrap_elements(elements, per_line, &config.format).join(&indent)
         } else {
-            chan_info.name.to_string()
-        });
+            chan_info.format_array_range(start, end, &config.format)
+        };
+        // An empty array (NORD = 0) is printed as just its count
+        if scalar || !value.is_empty() {
+            components.push(value);
+        }
+        if let Some(stats) = stats {
+            components.push(stats);
+        }
+        if config.units {
+            if let Some(units) = chan_info.units.as_ref().filter(|u| !u.is_empty()) {
+                components.push(units.clone());
+            }
+        }
+        if config.wide {
+            components.push(chan_info.format_alarm());
+        }
+        if config.limits && chan_info.limits.is_some() {
+            components.push(chan_info.format_limits());
+        }
+
+        let mut lines = vec![components.join(&config.format.separator)];
+        lines.extend(dump.unwrap_or_default());
+        lines.join("\n")
+    }
+
+    // Whatever can't be known without the value (type, time stamp, count, alarm) is a placeholder too
+    fn placeholder(&mut self, name: &str, placeholder: &str) -> String {
+        let config = self.config;
+        let mut components = vec![];
+        if config.show_name && !config.terse && !config.header {
+            components.push(format!("{name:<width$}", width = config.name_width));
+        }
+        if config.show_type && !config.terse {
+            components.push(placeholder.into());
+        }
+        if config.wide {
+            components.push(placeholder.into());
+        }
+        if config.always_count && !config.terse {
+            components.push(placeholder.into());
+        }
+        components.push(placeholder.into());
+        if config.wide {
+            components.push(placeholder.into());
+        }
+        components.join(&config.format.separator)
     }
+}
 
-    if config.wide {
-        components.push(chan_info.format_stamp());
+// Fields that contain the separator, quotes or line breaks are quoted, doubling inner quotes
+fn csv_field(field: String, separator: &str) -> String {
+    if field.contains(separator) || field.contains(['"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
     }
+}
+
+// Array elements are expanded across columns
+struct CsvFormatter<'a> {
+    config: &'a Config,
+}
+
+impl OutputFormatter for CsvFormatter<'_> {
+    fn format(&mut self, chan_info: &Info) -> String {
+        let config = self.config;
+        let separator = &config.format.separator;
+        let mut fields = vec![chan_info.name.clone(), chan_info.format_stamp(&config.format)];
+        if chan_info.is_scalar() {
+            fields.push(chan_info.format_scalar(&config.format));
+        } else {
+            let (start, end) = array_range(chan_info, config);
+            fields.extend(chan_info.format_elements_range(start, end, &config.format));
+        }
 
-    if !scalar {
-        components.push(format!("{}", chan_info.elements));
+        let fields: Vec<_> = fields.into_iter().map(|f| csv_field(f, separator)).collect();
+        fields.join(separator)
     }
-    components.push(if scalar {
-        chan_info.format_scalar()
+
+    fn placeholder(&mut self, name: &str, placeholder: &str) -> String {
+        let separator = &self.config.format.separator;
+        [name, placeholder, placeholder]
+            .map(|f| csv_field(f.to_string(), separator))
+            .join(separator)
+    }
+}
+
+// The received data structures, for debugging
+struct RawFormatter<'a> {
+    config: &'a Config,
+}
+
+impl OutputFormatter for RawFormatter<'_> {
+    fn format(&mut self, chan_info: &Info) -> String {
+        if self.config.show_name && !self.config.terse {
+            format!("{} {:?}", chan_info.name, chan_info.value)
+        } else {
+            format!("{:?}", chan_info.value)
+        }
+    }
+
+    fn placeholder(&mut self, name: &str, placeholder: &str) -> String {
+        if self.config.show_name && !self.config.terse {
+            format!("{name} {placeholder}")
+        } else {
+            placeholder.into()
+        }
+    }
+}
+
+// A single object per PV. print_json puts them together
+struct JsonFormatter<'a> {
+    config: &'a Config,
+}
+
+impl OutputFormatter for JsonFormatter<'_> {
+    fn format(&mut self, chan_info: &Info) -> String {
+        chan_info.to_json(&self.config.format)
+    }
+
+    fn placeholder(&mut self, name: &str, placeholder: &str) -> String {
+        format!("{{\"name\":{},\"value\":{}}}", json_string(name), json_string(placeholder))
+    }
+}
+
+fn is_timeout(e: &UnifiedError) -> bool {
+    matches!(e, UnifiedError::ConnectTimeout(_))
+}
+
+/*
+   A single PV is printed as an object, and several of them as an array of objects, even if only
+   one of them made it. PVs that couldn't be read get an object with their error, instead of a
+   value, or the placeholder if there's one
+*/
+fn print_json(
+    out: &mut dyn Write,
+    formatter: &mut dyn OutputFormatter,
+    rows: &[(&String, Option<&Info>)],
+    failed: &FailedChannels,
+    config: &Config,
+) -> io::Result<()> {
+    let objects: Vec<_> = rows
+        .iter()
+        .filter_map(|(name, ch)| match ch {
+            Some(ch) => Some(formatter.format(ch)),
+            None if config.placeholder.is_some() => {
+                Some(formatter.placeholder(name, config.placeholder.as_deref().unwrap_or_default()))
+            }
+            None => failed
+                .iter()
+                .find(|(failed_name, _)| failed_name == *name)
+                .filter(|(_, e)| !(config.quiet_missing && is_timeout(e)))
+                .map(|(_, e)| {
+                    let error = json_string(&e.to_string());
+                    format!("{{\"name\":{},\"error\":{error}}}", json_string(name))
+                }),
+        })
+        .collect();
+    if config.names.len() > 1 {
+        writeln!(out, "[{}]", objects.join(","))
     } else {
-        chan_info.format_array_full()
-    });
+        objects.iter().try_for_each(|object| writeln!(out, "{object}"))
+    }
+}
+
+// One line per name, in the order they were given. Nothing is sent to the network
+fn print_validation(out: &mut dyn Write, config: &Config) -> UnifiedResult<()> {
+    let width = config.name_width;
+    let mut invalid = 0;
+    for name in &config.names {
+        match validate_pv_name(name) {
+            Err(e) => {
+                invalid += 1;
+                writeln!(out, "{name:<width$} invalid: {e}")
+            }
+            Ok(()) => writeln!(out, "{name:<width$} valid"),
+        }
+        .and_then(|()| out.flush())
+        .map_err(|error| UnifiedError::Misc(format!("Can't write the results: {error}")))?;
+    }
+
+    if invalid == 0 {
+        Ok(())
+    } else {
+        Err(UnifiedError::Misc(format!("{invalid} invalid PV name(s)")))
+    }
+}
+
+// Stdout, or the --output file
+fn open_output(config: &Config) -> UnifiedResult<Box<dyn Write>> {
+    match &config.output {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(config.append)
+            .truncate(!config.append)
+            .open(path)
+            .map(|file| Box::new(BufWriter::new(file)) as Box<dyn Write>)
+            .map_err(|error| UnifiedError::Misc(format!("Can't open {path}: {error}"))),
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+async fn fetch_info(channel: &mut Channel, opts: FetchOpts) -> UnifiedResult<Info> {
+    if opts.ctrl {
+        return grab_full_info_as(channel, opts.dbr).await;
+    }
+    let mut info = grab_info_as(channel, opts.dbr).await?;
+    // Only floats have a precision. Without it, they're printed with the default one
+    if opts.precision && matches!(info.value.field_id(), FieldId::Float | FieldId::Double) {
+        match grab_precision(channel).await {
+            Ok(prec) => info.precision = prec,
+            Err(e) => log(1, format!("{}: can't read the precision: {e}", info.name)),
+        }
+    }
+    Ok(info)
+}
+
+// What collect_sync and collect_async need to know to connect and fetch each channel
+#[derive(Clone, Copy)]
+struct FetchOpts {
+    timeout: u64,
+    read_timeout: Duration,
+    retries: u32,
+    ctrl: bool,
+    // Ask for the record's precision, if there's no Ctrl request to take it from
+    precision: bool,
+    // Overrides the native type of the channels
+    dbr: Option<FieldId>,
+}
+
+async fn fetch_channel(channel: &mut Channel, opts: FetchOpts) -> UnifiedResult<Info> {
+    connect_channel(channel, opts).await?;
+    read_channel(channel, opts).await
+}
+
+async fn connect_channel(channel: &mut Channel, opts: FetchOpts) -> UnifiedResult<()> {
+    let name = channel.name().to_string_lossy().to_string();
+    let start = Instant::now();
+    if let Err(e) = connect_with_retry(channel, opts.timeout, opts.retries).await {
+        log(1, format!("{name}: connect timed out"));
+        return Err(e);
+    }
+    log(1, format!("{name}: connected after {:?}", start.elapsed()));
+    Ok(())
+}
+
+async fn read_channel(channel: &mut Channel, opts: FetchOpts) -> UnifiedResult<Info> {
+    let name = channel.name().to_string_lossy().to_string();
+    if let (Ok(tp), Ok(count)) = (channel.field_type(), channel.element_count()) {
+        log(2, format!("{name}: native type {tp:?}, {count} element(s)"));
+    }
+
+    let start = Instant::now();
+    let info = read_with_timeout(&name, opts.read_timeout, fetch_info(channel, opts)).await;
+    log(1, format!("{name}: request completed in {:?}", start.elapsed()));
+    info
+}
+
+/*
+   Results of the collection, tagged with the index of their channel. They're gathered as they
+   come, so that whatever was done is still there if the collection is cut short by --deadline.
+*/
+#[derive(Default)]
+struct Collected {
+    info: Vec<(usize, Info)>,
+    failed: Vec<(usize, (String, UnifiedError))>,
+    // Handed back by the collectors when done with them, for the next --poll round
+    channels: Vec<(usize, (Channel, FetchOpts))>,
+}
+
+impl Collected {
+    fn push(&mut self, idx: usize, name: String, res: UnifiedResult<Info>) {
+        match res {
+            Ok(info) => self.info.push((idx, info)),
+            Err(e) => self.failed.push((idx, (name, e))),
+        }
+    }
 
-    println!("{}", components.join(" "));
+    fn take_channels(&mut self) -> Vec<(Channel, FetchOpts)> {
+        self.channels.sort_by_key(|(idx, _)| *idx);
+        self.channels.drain(..).map(|(_, channel)| channel).collect()
+    }
+
+    // Back in the same order as `names`. The ones with no result at all ran out of time
+    fn finish(mut self, names: &[String]) -> (Vec<Info>, FailedChannels) {
+        let done: HashSet<usize> = self
+            .info
+            .iter()
+            .map(|(idx, _)| *idx)
+            .chain(self.failed.iter().map(|(idx, _)| *idx))
+            .collect();
+        for (idx, name) in names.iter().enumerate().filter(|(idx, _)| !done.contains(idx)) {
+            let error = UnifiedError::Misc("Deadline exceeded".into());
+            self.failed.push((idx, (name.clone(), error)));
+        }
+        self.info.sort_by_key(|(idx, _)| *idx);
+        self.failed.sort_by_key(|(idx, _)| *idx);
+        (
+            self.info.into_iter().map(|(_, info)| info).collect(),
+            self.failed.into_iter().map(|(_, fail)| fail).collect(),
+        )
+    }
 }
 
-async fn collect_sync(mut channels: Vec<Channel>, timeout: u64) -> UnifiedResult<Vec<Info>> {
-    wait_connect(&mut channels, timeout).await?;
+/*
+   The channels are all waited for at once, each with its own timeout, so that the PVs that are
+   missing don't add up their timeouts. Only those get retried (with what's left of -r), and then
+   the values are read one by one. A PV that fails doesn't stop the rest: its error is collected
+   along with the values.
+*/
+async fn collect_sync(mut channels: Vec<(Channel, FetchOpts)>, collected: &mut Collected) {
+    let waits = channels
+        .iter_mut()
+        .map(|(ch, opts)| wait_connect(std::slice::from_mut(ch), opts.timeout));
+    let first_waits = join_all(waits).await;
 
-    let mut result = vec![];
-    for ch in channels {
-        result.push(grab_info(ch).await?);
+    for (idx, ((mut ch, opts), first_wait)) in channels.into_iter().zip(first_waits).enumerate() {
+        let connected = match first_wait {
+            Err(_) if opts.retries > 0 => {
+                connect_channel(&mut ch, FetchOpts { retries: opts.retries - 1, ..opts }).await
+            }
+            Err(e) => {
+                log(1, format!("{}: connect timed out", ch.name().to_string_lossy()));
+                Err(e)
+            }
+            Ok(()) => Ok(()),
+        };
+        let res = match connected {
+            Ok(()) => read_channel(&mut ch, opts).await,
+            Err(e) => Err(e),
+        };
+        collected.push(idx, ch.name().to_string_lossy().to_string(), res);
+        collected.channels.push((idx, (ch, opts)));
     }
-    Ok(result)
 }
 
-async fn collect_async(channels: Vec<Channel>, timeout: u64) -> UnifiedResult<Vec<Info>> {
+/*
+   At most `max_concurrent` channels are connected and read at the same time. As with
+   collect_sync, the errors are collected along with the values, and the rest keep going.
+*/
+async fn collect_async(
+    channels: Vec<(Channel, FetchOpts)>,
+    max_concurrent: usize,
+    collected: &mut Collected,
+) {
     let mut set = JoinSet::new();
+    let permits = Arc::new(Semaphore::new(max_concurrent));
 
-    for mut ch in channels {
+    for (idx, (mut ch, opts)) in channels.into_iter().enumerate() {
+        let permits = permits.clone();
         set.spawn(async move {
-            let sleeper = sleep(Duration::from_millis(timeout));
-            tokio::pin!(sleeper);
-
-            select! {
-                () = ch.connected() => Ok(()),
-                () = &mut sleeper =>
-                    Err(UnifiedError::Misc("Channel connect timed out: some PV(s) not found.".into())),
-            }?;
-            grab_info(ch).await
+            // The semaphore is never closed, so this can't fail
+            let _permit = permits.acquire_owned().await.unwrap();
+            let res = fetch_channel(&mut ch, opts).await;
+            (idx, ch, opts, res)
         });
     }
 
-    let mut result = vec![];
-
+    // Dropping the set, if the deadline passes, aborts the tasks still running
     while let Some(task_res) = set.join_next().await {
-        if let Ok(res) = task_res {
-            result.push(res?);
+        if let Ok((idx, ch, opts, res)) = task_res {
+            collected.push(idx, ch.name().to_string_lossy().to_string(), res);
+            collected.channels.push((idx, (ch, opts)));
         }
     }
+}
+
+// Prints one round of results, in the format chosen
+fn print_results(
+    out: &mut dyn Write,
+    config: &Config,
+    stamps: &mut TimestampFormatter,
+    info: &[Info],
+    failed: &FailedChannels,
+) -> UnifiedResult<()> {
+    // Every requested PV, in the order they were given, with its value if it could be read
+    let mut read = info.iter().peekable();
+    let rows: Vec<_> = config
+        .names
+        .iter()
+        .map(|name| (name, read.next_if(|ch| &ch.name == name)))
+        .collect();
 
-    Ok(result)
+    write_results(out, config, formatter(config, stamps).as_mut(), &rows, failed)
+        .and_then(|()| out.flush())
+        .map_err(|error| UnifiedError::Misc(format!("Can't write the results: {error}")))
 }
 
-async fn run(config: Config) -> UnifiedResult<()> {
-    let timeout = (config.wait_time * 1000.0) as u64;
-    let ctx = Context::new().map_err(UnifiedError::CaError)?;
-    let channels = get_channels(&ctx, &config.names)?;
+fn write_results(
+    out: &mut dyn Write,
+    config: &Config,
+    formatter: &mut dyn OutputFormatter,
+    rows: &[(&String, Option<&Info>)],
+    failed: &FailedChannels,
+) -> io::Result<()> {
+    if let Some(header) = formatter.header() {
+        writeln!(out, "{header}")?;
+    }
+    if config.json {
+        return print_json(out, formatter, rows, failed, config);
+    }
+
+    // With a placeholder, every requested PV gets a line
+    let mut rows = rows.iter().filter_map(|&(name, ch)| match (ch, &config.placeholder) {
+        (Some(ch), _) => Some((name, formatter.format(ch))),
+        (None, Some(placeholder)) => Some((name, formatter.placeholder(name, placeholder))),
+        (None, None) => None,
+    });
+    if config.raw || config.csv {
+        return rows.try_for_each(|(_, line)| writeln!(out, "{line}"));
+    }
+    rows.enumerate().try_for_each(|(i, (name, line))| {
+        if config.group && i > 0 {
+            writeln!(out)?;
+        }
+        if config.header {
+            writeln!(out, "{name}")?;
+        }
+        writeln!(out, "{line}")
+    })
+}
+
+// The failures to tell about: --quiet-missing leaves out the PVs that didn't connect
+fn reported(failed: &FailedChannels, quiet_missing: bool) -> Vec<&(String, UnifiedError)> {
+    failed
+        .iter()
+        .filter(|(_, e)| !(quiet_missing && is_timeout(e)))
+        .collect()
+}
 
-    let info = if config.asynchronous {
-        collect_async(channels, timeout).await?
+// The individual errors have been reported already. This just sets the exit code
+fn outcome(info: &[Info], failed: FailedChannels) -> UnifiedResult<()> {
+    if info.is_empty() {
+        Err(UnifiedError::NoPvs)
+    } else if failed.is_empty() {
+        Ok(())
+    } else if failed.iter().all(|(_, e)| is_timeout(e)) {
+        Err(UnifiedError::ConnectTimeout(failed.into_iter().map(|(name, _)| name).collect()))
     } else {
-        collect_sync(channels, timeout).await?
+        Err(UnifiedError::Misc(format!("{} PV(s) could not be read", failed.len())))
+    }
+}
+
+async fn run(config: Config) -> UnifiedResult<()> {
+    let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+    // Only --poll handles Ctrl-C: otherwise it should just kill the process, as usual
+    let interrupted = match config.poll {
+        Some(_) => Either::Left(install_ctrl_c()),
+        None => Either::Right(pending()),
     };
+    tokio::pin!(interrupted);
+    // Opened first, so that a bad path fails before any work is done
+    let mut out = open_output(&config)?;
+    if config.validate {
+        return print_validation(&mut out, &config);
+    }
+    let ca = CaEnv::from_env();
+    let opts = FetchOpts {
+        timeout: (config.wait_time * 1000.0) as u64,
+        read_timeout: Duration::from_secs_f32(config.read_timeout),
+        retries: config.retries,
+        ctrl: config.needs_ctrl(),
+        precision: config.needs_precision(),
+        dbr: config.dbr,
+    };
+    let ctx = build_context(&ca)?;
+    let (channels, failed) = get_channels(&ctx, &config.names)?;
+    for ch in &channels {
+        log(1, format!("{}: channel created", ch.name().to_string_lossy()));
+    }
+    // In JSON mode the errors go with the values
+    if !config.json {
+        report_failed(&failed);
+    }
+
+    let mut channels: Vec<_> = channels
+        .into_iter()
+        .map(|ch| {
+            let name = ch.name().to_string_lossy();
+            let opts = match config.timeouts.get(name.as_ref()) {
+                Some(secs) => FetchOpts { timeout: (secs * 1000.0) as u64, ..opts },
+                None => opts,
+            };
+            (ch, opts)
+        })
+        .collect();
+
+    let names: Vec<_> = channels
+        .iter()
+        .map(|(ch, _)| ch.name().to_string_lossy().to_string())
+        .collect();
+
+    // Just once, unless polling. Polls reuse the channels, which stay connected in between
+    loop {
+        if config.clear {
+            let _ = write!(out, "{CLEAR_SCREEN}");
+        }
+        let mut collected = Collected::default();
+        let collect = async {
+            if config.asynchronous {
+                collect_async(channels, config.max_concurrent, &mut collected).await
+            } else {
+                collect_sync(channels, &mut collected).await
+            }
+        };
+        match config.deadline {
+            Some(secs) => {
+                if timeout(Duration::from_secs_f32(secs), collect).await.is_err() {
+                    log(1, format!("Deadline of {secs}s exceeded"));
+                }
+            }
+            None => collect.await,
+        }
+        channels = collected.take_channels();
+        let (info, failed_reading) = collected.finish(&names);
+        // In JSON mode, they're reported along with the values
+        if !config.json {
+            for (name, e) in reported(&failed_reading, config.quiet_missing) {
+                eprintln!("{name}: {e}");
+            }
+        }
+        let mut all_failed = failed.clone();
+        all_failed.extend(failed_reading);
 
-    for ch in info {
-        print_formatted(&ch, &config);
+        print_results(&mut out, &config, &mut stamps, &info, &all_failed)?;
+
+        let Some(secs) = config.poll else {
+            return outcome(&info, all_failed);
+        };
+        select! {
+            () = sleep(Duration::from_secs_f32(secs)) => (),
+            () = &mut interrupted => return Err(UnifiedError::Interrupted),
+        }
     }
+}
 
-    Ok(())
+// What's left to say when `run` fails: the PVs that failed have been reported one by one already
+fn summary(e: &UnifiedError) -> String {
+    match e {
+        UnifiedError::ConnectTimeout(names) => format!("{} PV(s) not found", names.len()),
+        e => e.to_string(),
+    }
 }
 
 #[tokio::main]
-async fn main() {
-    if let Err(e) = get_arguments().and_then(run).await {
-        match e {
-            UnifiedError::Misc(msg) => eprintln!("{msg}"),
-            _ => eprintln!("{e:?}"),
+async fn main() -> ExitCode {
+    fn fail(e: UnifiedError) -> ExitCode {
+        eprintln!("{e}");
+        ExitCode::from(e.exit_code() as u8)
+    }
+
+    let config = match get_arguments().await {
+        Ok(config) => config,
+        Err(e) => return fail(e),
+    };
+    let quiet_missing = config.quiet_missing;
+    match run(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // With --quiet-missing, missing PVs are only reflected in the exit code
+        Err(e @ (UnifiedError::ConnectTimeout(_) | UnifiedError::NoPvs)) if quiet_missing => {
+            ExitCode::from(e.exit_code() as u8)
+        }
+        // Ctrl-C is how --poll ends, so it's reflected only in the exit code
+        Err(e @ UnifiedError::Interrupted) => ExitCode::from(e.exit_code() as u8),
+        Err(e) => {
+            eprintln!("{}", summary(&e));
+            ExitCode::from(e.exit_code() as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epics_tools::{test_support::{time_array, time_value}, types::RawValue};
+    use epics_ca::types::EpicsString;
+
+    fn config(args: &[&str]) -> Config {
+        parse_arguments(["rcaget"].iter().chain(args)).unwrap()
+    }
+
+    // What rcaget prints for `info`, run with `args`
+    fn output(args: &[&str], info: &Info) -> String {
+        let config = config(args);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let text = formatter(&config, &mut stamps).format(info);
+        text
+    }
+
+    // Everything rcaget writes for `rows`, run with `args`
+    fn results(args: &[&str], rows: &[(&String, Option<&Info>)], failed: &FailedChannels) -> String {
+        let config = config(args);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let mut out = vec![];
+        write_results(&mut out, &config, formatter(&config, &mut stamps).as_mut(), rows, failed).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn long(value: i32) -> Info {
+        Info::new("pv".into(), 1, RawValue::Long(time_value(value)))
+    }
+
+    #[test]
+    fn wide_mode_stamps_follow_the_timestamp_kind() {
+        let info = long(3);
+        let none = output(&["-a", "--timestamp", "n", "pv"], &info);
+        assert_eq!(none, format!("{:<30} 3 NO_ALARM NO_ALARM", "pv"));
+        let server = output(&["-a", "--timestamp", "s", "pv"], &info);
+        // The sample is stamped at the EPICS epoch
+        assert!(server.starts_with(&format!("{:<30} 19", "pv")), "{server}");
+        assert!(server.ends_with(" 3 NO_ALARM NO_ALARM"), "{server}");
+    }
+
+    #[test]
+    fn csv_rows() {
+        let info = long(3);
+        let stamp = info.format_stamp(&FormatOpts::default());
+        assert_eq!(output(&["--csv", "pv"], &info), format!("pv,{stamp},3"));
+        assert_eq!(output(&["-F", ";", "--csv", "pv"], &info), format!("pv;{stamp};3"));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain".into(), ","), "plain");
+        assert_eq!(csv_field("a,b".into(), ","), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\"".into(), ","), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn count_limits_the_elements() {
+        let info = Info::new("pv".into(), 5, RawValue::LongArray(time_array(&[1, 2, 3])));
+        assert_eq!(output(&["--name-width=0", "pv"], &info), "pv 3 1 2 3");
+        assert_eq!(output(&["-#", "2", "--name-width=0", "pv"], &info), "pv 2 1 2");
+        // Up to the capacity of the channel, padding with zeros
+        assert_eq!(output(&["-#", "9", "--name-width=0", "pv"], &info), "pv 5 1 2 3 0 0");
+    }
+
+    #[test]
+    fn units_follow_the_value() {
+        let mut info = long(3);
+        info.units = Some("mm".into());
+        assert_eq!(output(&["-u", "pv"], &info), format!("{:<30} 3 mm", "pv"));
+        assert_eq!(output(&["pv"], &info), format!("{:<30} 3", "pv"));
+        info.units = Some(String::new());
+        assert_eq!(output(&["-u", "pv"], &info), format!("{:<30} 3", "pv"));
+    }
+
+    #[test]
+    fn no_name_keeps_the_rest_of_the_layout() {
+        assert_eq!(output(&["--no-name", "pv"], &long(3)), "3");
+        assert_eq!(output(&["--no-name", "-a", "--timestamp", "n", "pv"], &long(3)), "3 NO_ALARM NO_ALARM");
+        let array = Info::new("pv".into(), 2, RawValue::LongArray(time_array(&[1, 2])));
+        // Unlike -t, which drops the count too
+        assert_eq!(output(&["--no-name", "pv"], &array), "2 1 2");
+        assert_eq!(output(&["-t", "pv"], &array), "1 2");
+    }
+
+    #[test]
+    fn results_come_back_in_the_order_requested() {
+        let names = ["a", "b", "c", "d"].map(String::from);
+        let mut collected = Collected::default();
+        for idx in [2, 0, 1] {
+            let mut info = long(idx as i32);
+            info.name = names[idx].clone();
+            collected.push(idx, names[idx].clone(), Ok(info));
+        }
+        collected.push(3, names[3].clone(), Err(UnifiedError::Misc("failed".into())));
+        let (info, failed) = collected.finish(&names);
+        let order: Vec<_> = info.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(order, ["a", "b", "c"]);
+        assert_eq!(failed[0].0, "d");
+
+        let mut collected = Collected::default();
+        for idx in [3, 2, 1, 0] {
+            collected.push(idx, names[idx].clone(), Err(UnifiedError::Misc("failed".into())));
         }
+        let (_, failed) = collected.finish(&names);
+        let order: Vec<_> = failed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(order, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn terse_arrays_are_just_their_values() {
+        let array = Info::new("pv".into(), 3, RawValue::LongArray(time_array(&[1, 2, 3])));
+        assert_eq!(output(&["-t", "pv"], &array), "1 2 3");
+        assert_eq!(output(&["-t", "-F", ",", "pv"], &array), "1,2,3");
+        assert_eq!(output(&["-t", "pv"], &long(4)), "4");
+    }
+
+    #[test]
+    fn empty_arrays_print_just_their_count() {
+        let empty = Info::new("pv".into(), 5, RawValue::DoubleArray(time_array::<f64>(&[])));
+        assert_eq!(output(&["--name-width=0", "pv"], &empty), "pv 0");
+        assert_eq!(output(&["-t", "pv"], &empty), "");
+        assert!(empty.to_json(&config(&["pv"]).format).contains(r#""value":[]"#));
+    }
+
+    #[test]
+    fn read_timeout_defaults_to_the_connection_one() {
+        assert_eq!(config(&["-w", "2", "pv"]).read_timeout, 2.0);
+        let config = config(&["-w", "2", "--read-timeout", "0.5", "pv"]);
+        assert_eq!((config.wait_time, config.read_timeout), (2.0, 0.5));
+    }
+
+    #[test]
+    fn the_exit_code_depends_on_what_failed() {
+        let timeout = |name: &str| (name.to_string(), UnifiedError::ConnectTimeout(vec![name.into()]));
+        assert!(outcome(&[long(1)], vec![]).is_ok());
+        let res = outcome(&[long(1)], vec![timeout("a"), timeout("b")]);
+        assert!(matches!(res, Err(UnifiedError::ConnectTimeout(names)) if names == ["a", "b"]));
+        let failed = vec![timeout("a"), ("b".into(), UnifiedError::Misc("no read access".into()))];
+        assert!(matches!(outcome(&[long(1)], failed), Err(UnifiedError::Misc(msg)) if msg == "2 PV(s) could not be read"));
+        assert!(matches!(outcome(&[], vec![timeout("a")]), Err(UnifiedError::NoPvs)));
+    }
+
+    #[test]
+    fn raw_output_is_the_debug_dump() {
+        let info = long(3);
+        assert_eq!(output(&["--raw", "pv"], &info), format!("pv {:?}", info.value));
+        assert_eq!(output(&["--raw", "-t", "pv"], &info), format!("{:?}", info.value));
+    }
+
+    #[test]
+    fn concurrency_limit() {
+        assert_eq!(config(&["pv"]).max_concurrent, 256);
+        assert_eq!(config(&["-c", "--max-concurrent", "4", "pv"]).max_concurrent, 4);
+        assert!(count_in_range("0").is_err());
+    }
+
+    #[test]
+    fn names_are_padded_for_arrays_too() {
+        let array = Info::new("pv".into(), 2, RawValue::LongArray(time_array(&[1, 2])));
+        assert_eq!(output(&["--name-width", "6", "pv"], &array), "pv     2 1 2");
+        assert_eq!(output(&["--name-width", "6", "pv"], &long(1)), "pv     1");
+    }
+
+    #[test]
+    fn quiet_missing_drops_only_the_timeouts() {
+        let failed = vec![
+            ("a".to_string(), UnifiedError::ConnectTimeout(vec!["a".into()])),
+            ("b".to_string(), UnifiedError::Misc("bad".into())),
+        ];
+        let names = |shown: Vec<&(String, UnifiedError)>| shown.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(reported(&failed, true)), ["b"]);
+        assert_eq!(names(reported(&failed, false)), ["a", "b"]);
+    }
+
+    #[test]
+    fn output_file_is_truncated_unless_appending() {
+        let path = std::env::temp_dir().join(format!("rcaget-test-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let write = |args: &[&str], text: &str| {
+            let mut out = open_output(&config(args)).unwrap();
+            write!(out, "{text}").and_then(|()| out.flush()).unwrap();
+        };
+        write(&["--output", path, "pv"], "old");
+        write(&["--output", path, "pv"], "a");
+        write(&["--output", path, "--append", "pv"], "b");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "ab");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn the_type_follows_the_name() {
+        assert_eq!(output(&["--show-type", "--name-width=0", "pv"], &long(3)), "pv DBF_LONG 3");
+        // Terse mode is just the value
+        assert_eq!(output(&["--show-type", "-t", "pv"], &long(3)), "3");
+    }
+
+    #[test]
+    fn slices_of_arrays() {
+        let array = Info::new("pv".into(), 5, RawValue::LongArray(time_array(&[0, 1, 2, 3, 4])));
+        assert_eq!(output(&["--slice", "1:3", "--name-width=0", "pv"], &array), "pv 2 1 2");
+        // Clamped to the length of the value
+        assert_eq!(output(&["--slice", "3:9", "-t", "pv"], &array), "3 4");
+    }
+
+    #[test]
+    fn groups_and_headers() {
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let mut info = [long(1), long(2)];
+        info[0].name = a.clone();
+        info[1].name = b.clone();
+        let rows = [(&a, Some(&info[0])), (&b, Some(&info[1]))];
+        assert_eq!(results(&["--group", "--name-width=0", "a", "b"], &rows, &vec![]), "a 1\n\nb 2\n");
+        assert_eq!(results(&["--header", "a", "b"], &rows, &vec![]), "a\n1\nb\n2\n");
+    }
+
+    #[test]
+    fn validation_lists_every_name() {
+        let long = "x".repeat(61);
+        let config = config(&["--validate", "--name-width=0", "ok:pv", "bad\tpv", &long]);
+        let mut out = vec![];
+        let res = print_validation(&mut out, &config);
+        assert!(matches!(res, Err(UnifiedError::Misc(msg)) if msg == "2 invalid PV name(s)"));
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "ok:pv valid");
+        assert!(lines[1].starts_with("bad\tpv invalid: Invalid character"));
+        assert!(lines[2].ends_with("invalid: The record name is longer than 60 characters"));
+    }
+
+    #[test]
+    fn wrapped_arrays_are_indented() {
+        let array = Info::new("pv".into(), 5, RawValue::LongArray(time_array(&[1, 2, 3, 4, 5])));
+        assert_eq!(output(&["--wrap", "2", "--name-width=0", "pv"], &array), "pv 5 1 2\n     3 4\n     5");
+        assert_eq!(output(&["--wrap", "2", "-t", "pv"], &array), "1 2\n3 4\n5");
+    }
+
+    #[test]
+    fn placeholders_for_the_pvs_not_read() {
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let mut one = long(1);
+        one.name = a.clone();
+        let rows = [(&a, Some(&one)), (&b, None)];
+        assert_eq!(results(&["--default", "--name-width=0", "a", "b"], &rows, &vec![]), "a 1\nb N/A\n");
+        assert_eq!(results(&["--default=?", "-t", "a", "b"], &rows, &vec![]), "1\n?\n");
+    }
+
+    fn placeholder(args: &[&str]) -> String {
+        let config = config(args);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let line = formatter(&config, &mut stamps).placeholder("pv", "N/A");
+        line
+    }
+
+    #[test]
+    fn placeholder_fills_every_column() {
+        assert_eq!(placeholder(&["--default", "--name-width=0", "pv"]), "pv N/A");
+        assert_eq!(
+            placeholder(&["--default", "--name-width=0", "-a", "--show-type", "--always-count", "pv"]),
+            "pv N/A N/A N/A N/A N/A"
+        );
+        assert_eq!(placeholder(&["--default", "-t", "-a", "pv"]), "N/A N/A N/A");
+    }
+
+    #[test]
+    fn placeholder_in_every_output_mode() {
+        assert_eq!(placeholder(&["--default", "--csv", "pv"]), "pv,N/A,N/A");
+        assert_eq!(placeholder(&["--default", "--raw", "pv"]), "pv N/A");
+        assert_eq!(placeholder(&["--default", "--json", "pv"]), r#"{"name":"pv","value":"N/A"}"#);
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let rows = [(&a, None), (&b, None)];
+        assert_eq!(results(&["--default=-", "--csv", "a", "b"], &rows, &vec![]), "a,-,-\nb,-,-\n");
+    }
+
+    #[test]
+    fn pvs_left_at_the_deadline_fail() {
+        let names = ["a".to_string(), "b".to_string()];
+        let mut collected = Collected::default();
+        collected.push(0, "a".into(), Ok(long(1)));
+        let (info, failed) = collected.finish(&names);
+        assert_eq!(info.len(), 1);
+        assert!(matches!(&failed[..], [(name, UnifiedError::Misc(msg))] if name == "b" && msg == "Deadline exceeded"));
+    }
+
+    #[test]
+    fn json_errors_are_objects_too() {
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let failed = vec![(b.clone(), UnifiedError::Misc("say \"no\"".into()))];
+        let one = results(&["--json", "b"], &[(&b, None)], &failed);
+        assert_eq!(one, "{\"name\":\"b\",\"error\":\"say \\\"no\\\"\"}\n");
+        // Several PVs make an array, even when only one of them is left
+        let mut info = long(1);
+        info.name = a.clone();
+        let many = results(&["--json", "a", "b"], &[(&a, Some(&info)), (&b, None)], &failed);
+        assert!(many.starts_with("[{\"name\":\"a\","), "{many}");
+        assert!(many.ends_with(",{\"name\":\"b\",\"error\":\"say \\\"no\\\"\"}]\n"), "{many}");
+        // Even when the other is left out
+        let missing = vec![(b.clone(), UnifiedError::ConnectTimeout(vec![b.clone()]))];
+        let rows = [(&a, Some(&info)), (&b, None)];
+        let left = results(&["--json", "--quiet-missing", "a", "b"], &rows, &missing);
+        assert!(left.starts_with("[{\"name\":\"a\",") && left.ends_with("}]\n"), "{left}");
+    }
+
+    #[test]
+    fn polling_options() {
+        let config = config(&["--poll", "0.5", "--clear", "pv"]);
+        assert_eq!((config.poll, config.clear), (Some(0.5), true));
+    }
+
+    #[test]
+    fn scalars_can_show_their_count() {
+        assert_eq!(output(&["--always-count", "--name-width=0", "pv"], &long(3)), "pv 1 3");
+        assert_eq!(output(&["--always-count", "-t", "pv"], &long(3)), "3");
+    }
+
+    #[test]
+    fn c_only_reads_concurrently() {
+        let (sync, concurrent) = (config(&["pv"]), config(&["-c", "pv"]));
+        assert!(!sync.asynchronous && concurrent.asynchronous);
+        // Everything else about the read stays the same
+        assert_eq!((sync.dbr, sync.read_timeout), (concurrent.dbr, concurrent.read_timeout));
+    }
+
+    #[test]
+    fn statistics_after_or_instead_of_the_values() {
+        let array = Info::new("pv".into(), 3, RawValue::LongArray(time_array(&[1, 2, 6])));
+        let with_values = output(&["--stats", "-p", "1", "--name-width=0", "pv"], &array);
+        assert_eq!(with_values, "pv 3 1 2 6 min=1.0 max=6.0 mean=3.0 count=3");
+        assert_eq!(output(&["--stats-only", "-p", "1", "--name-width=0", "pv"], &array), "pv min=1.0 max=6.0 mean=3.0 count=3");
+        // Scalars have none
+        assert_eq!(output(&["--stats", "--name-width=0", "pv"], &long(3)), "pv 3");
+    }
+
+    #[test]
+    fn one_formatter_per_output_mode() {
+        let info = long(3);
+        let lines: Vec<_> = [&[][..], &["--csv"], &["--raw"], &["--json"]]
+            .iter()
+            .map(|mode| output(&[mode, &["--name-width=0", "pv"][..]].concat(), &info))
+            .collect();
+        assert_eq!(lines[0], "pv 3");
+        assert!(lines[1].starts_with("pv,") && lines[1].ends_with(",3"), "{}", lines[1]);
+        assert_eq!(lines[2], format!("pv {:?}", info.value));
+        assert!(lines[3].starts_with(r#"{"name":"pv","#), "{}", lines[3]);
+    }
+
+    #[test]
+    fn values_read_as_strings_print_the_server_text() {
+        assert_eq!(config(&["-d", "DBR_STRING", "pv"]).dbr, Some(FieldId::String));
+        let text = |s: &CStr| EpicsString::from_cstr(s).unwrap();
+        let array = Info::new("pv".into(), 2, RawValue::StringArray(time_array(&[text(c"1.500"), text(c"-2")])));
+        assert_eq!(output(&["--name-width=0", "pv"], &array), "pv 2 1.500 -2");
+    }
+
+    #[test]
+    fn one_element_per_line() {
+        let array = Info::new("pv".into(), 3, RawValue::LongArray(time_array(&[1, 2, 3])));
+        assert_eq!(output(&["--one-per-line", "--name-width=0", "pv"], &array), "pv[0] 1\npv[1] 2\npv[2] 3");
+        // The indices are those of the whole array
+        assert_eq!(output(&["--one-per-line", "--slice", "1:", "--name-width=0", "pv"], &array), "pv[1] 2\npv[2] 3");
+        assert_eq!(output(&["--one-per-line", "--name-width=0", "pv"], &long(4)), "pv 4");
+    }
+
+    #[test]
+    fn match_filters_the_names() {
+        assert_eq!(config(&["--match", "TEMP$", "a:TEMP", "a:PRES", "b:TEMP"]).names, ["a:TEMP", "b:TEMP"]);
+        let res = parse_arguments(["rcaget", "--match", "^x", "a", "b"]);
+        assert!(matches!(res, Err(UnifiedError::Misc(msg)) if msg == "No PV names match '^x'"));
+    }
+
+    #[test]
+    fn precision_is_read_only_for_formatted_values() {
+        let plain = config(&["pv"]);
+        assert!(plain.needs_precision() && !plain.needs_ctrl());
+        assert!(!config(&["-p", "3", "pv"]).needs_precision());
+        assert!(!config(&["--raw", "pv"]).needs_precision());
+        assert!(!config(&["--json", "pv"]).needs_precision());
+        assert!(config(&["--limits", "pv"]).needs_ctrl());
+    }
+
+    #[test]
+    fn summary_does_not_repeat_the_names() {
+        let e = UnifiedError::ConnectTimeout(vec!["a".into(), "b".into()]);
+        assert_eq!(summary(&e), "2 PV(s) not found");
+        assert_eq!(summary(&UnifiedError::NoPvs), "No PVs found");
     }
 }
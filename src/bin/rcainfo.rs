@@ -0,0 +1,172 @@
+use epics_tools::{
+    build_context, connection_state, describe, get_channels, report_failed, grab_full_info,
+    grab_graphic_info,
+};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{arg, Command};
+use epics_ca::Channel;
+use epics_tools::{
+    config::{ca_env::{self, CaEnv}, DEFAULT_WAIT_TIME, wait_time_in_range},
+    types::{field_type_name, ChannelInfo, ConnectionState, Info},
+    UnifiedResult
+};
+
+use futures::{future::join_all, TryFutureExt};
+use tokio::{select, time::sleep};
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+struct Config {
+    names: Vec<String>,
+    wait_time: f32,
+    // Request the Graphic metadata instead of the Ctrl one
+    graphic: bool,
+}
+
+async fn get_arguments() -> UnifiedResult<Config> {
+    let matches = Command::new(PKG_NAME)
+        .version(PKG_VERSION)
+        .author(PKG_AUTHORS)
+        .about("Rust cainfo")
+        .args([
+            arg!(wait: -w <sec> "-w <sec>: Wait time, specifies CA timeout")
+                .default_value(DEFAULT_WAIT_TIME)
+                .value_parser(wait_time_in_range),
+            arg!(addr_list: --"addr-list" <addrs> "Override EPICS_CA_ADDR_LIST"),
+            arg!(auto_addr: --"auto-addr" <yes_no> "Override EPICS_CA_AUTO_ADDR_LIST")
+                .value_parser(ca_env::yes_no),
+            arg!(graphic: --graphic "Show the display metadata only (no control limits)"),
+            arg!(names: <PV> ... "PV names"),
+        ])
+        .get_matches();
+
+    ca_env::apply_overrides(
+        matches.get_one::<String>("addr_list"),
+        matches.get_one::<bool>("auto_addr").copied(),
+    );
+
+    let names = matches
+        .get_many::<String>("names")
+        .unwrap()
+        .cloned()
+        .collect();
+    let wait_time = *matches.get_one::<f32>("wait").unwrap();
+
+    Ok(Config {
+        names,
+        wait_time,
+        graphic: matches.get_flag("graphic"),
+    })
+}
+
+fn print_field(label: &str, value: impl std::fmt::Display) {
+    println!("    {:<18}{}", format!("{label}:"), value);
+}
+
+// Worded like cainfo does
+fn access_label(read: bool, write: bool) -> &'static str {
+    match (read, write) {
+        (true, true) => "read, write",
+        (true, false) => "read, no write",
+        (false, true) => "no read, write",
+        (false, false) => "no read, no write",
+    }
+}
+
+fn print_info(desc: &ChannelInfo, info: &Info) {
+    print_field("Host", &desc.host);
+    print_field("Access", access_label(desc.read_access, desc.write_access));
+    print_field("Native data type", field_type_name(desc.field_type));
+    print_field("Element count", desc.element_count);
+    print_field("Alarm", info.format_alarm());
+    if let Some(units) = &info.units {
+        print_field("Units", units);
+    }
+    if let Some(precision) = info.precision {
+        print_field("Precision", precision);
+    }
+    if let Some(limits) = &info.limits {
+        print_field("Display limits", format!("{} .. {}", limits.display.0, limits.display.1));
+        if let Some((low, high)) = limits.control {
+            print_field("Control limits", format!("{low} .. {high}"));
+        }
+        print_field("Warning limits", format!("{} .. {}", limits.warning.0, limits.warning.1));
+        print_field("Alarm limits", format!("{} .. {}", limits.alarm.0, limits.alarm.1));
+    }
+    if !info.enum_strings.is_empty() {
+        println!("    Enum strings:");
+        for (i, label) in info.enum_strings.iter().enumerate() {
+            println!("        [{i:2}] {label}");
+        }
+    }
+}
+
+async fn inspect(channel: &mut Channel, graphic: bool) -> UnifiedResult<(ChannelInfo, Info)> {
+    let desc = describe(channel)?;
+    let info = if graphic {
+        grab_graphic_info(channel).await?
+    } else {
+        grab_full_info(channel).await?
+    };
+    Ok((desc, info))
+}
+
+async fn run(config: Config) -> UnifiedResult<()> {
+    let timeout = Duration::from_millis((config.wait_time * 1000.0) as u64);
+    let ctx = build_context(&CaEnv::from_env())?;
+    let (mut channels, failed) = get_channels(&ctx, &config.names)?;
+    report_failed(&failed);
+
+    // A channel that doesn't connect in time is not an error: we just report its state
+    let connected = channels.iter_mut().map(|ch| ch.connected());
+    select! {
+        _ = join_all(connected) => (),
+        () = sleep(timeout) => (),
+    }
+
+    for mut ch in channels {
+        println!("{}", ch.name().to_string_lossy());
+        let state = connection_state(&ch);
+        print_field("State", state);
+        if state == ConnectionState::Connected {
+            match inspect(&mut ch, config.graphic).await {
+                Ok((desc, info)) => print_info(&desc, &info),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match get_arguments().and_then(run).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code() as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_rights_are_spelled_out() {
+        assert_eq!(access_label(true, false), "read, no write");
+        assert_eq!(access_label(false, false), "no read, no write");
+    }
+
+    #[test]
+    fn connection_states() {
+        assert_eq!(ConnectionState::NeverConnected.to_string(), "never connected");
+        assert_eq!(ConnectionState::PreviouslyConnected.to_string(), "previously connected");
+    }
+}
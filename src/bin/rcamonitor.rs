@@ -0,0 +1,659 @@
+use epics_tools::{
+    build_context, connection_state, wait_connect_with_progress, get_channels, install_ctrl_c, read_pv_list, report_failed, monitor,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use std::ffi::OsString;
+
+use clap::{arg, Command};
+use epics_ca::types::EventMask;
+use epics_tools::{
+    config::{
+        ca_env::{self, CaEnv},
+        DEFAULT_EVENT_MASK, DEFAULT_NAME_WIDTH, DEFAULT_SEPARATOR, DEFAULT_STAMP_DIGITS, DEFAULT_TIMESTAMP, DEFAULT_WAIT_TIME, RETRY_BACKOFF_MS,
+        TimestampKind,
+        count_in_range, event_mask, name_pattern, stamp_digits, timestamp_kind, wait_time_in_range
+    },
+    types::{same_elements, ConnectionState, FormatOpts, Info, ParsedValue, SystemClock, TimestampFormatter},
+    UnifiedError,
+    UnifiedResult
+};
+
+use futures::TryFutureExt;
+use regex::Regex;
+use tokio::{select, sync::mpsc, task::JoinSet, time::{sleep, sleep_until, Instant}};
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+struct Config {
+    names: Vec<String>,
+    wait_time: f32,
+    mask: EventMask,
+    timestamp: TimestampKind,
+    format: FormatOpts,
+    name_width: usize,
+    // Skip updates that repeat the last value printed for the channel
+    on_change: bool,
+    // Print at most one update per channel this often
+    interval: Option<Duration>,
+    // Exit after printing this many updates, in total or, with per_channel, for every channel
+    count: Option<usize>,
+    per_channel: bool,
+    // Drop updates not newer than the last one printed for the channel
+    monotonic: bool,
+    // Report how many PVs are connected while waiting for them
+    progress: bool,
+    // Hold the updates back, and print only some of them per channel on exit
+    sample: Option<Sample>,
+}
+
+#[derive(Clone, Copy)]
+enum Sample {
+    First(usize),
+    Last(usize),
+}
+
+async fn get_arguments() -> UnifiedResult<Config> {
+    parse_arguments(std::env::args_os())
+}
+
+fn parse_arguments<I, T>(args: I) -> UnifiedResult<Config>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = Command::new(PKG_NAME)
+        .version(PKG_VERSION)
+        .author(PKG_AUTHORS)
+        .about("Rust camonitor")
+        .args([
+            arg!(wait: -w <sec> "-w <sec>: Wait time, specifies CA timeout")
+                .default_value(DEFAULT_WAIT_TIME)
+                .value_parser(wait_time_in_range),
+            arg!(addr_list: --"addr-list" <addrs> "Override EPICS_CA_ADDR_LIST"),
+            arg!(auto_addr: --"auto-addr" <yes_no> "Override EPICS_CA_AUTO_ADDR_LIST")
+                .value_parser(ca_env::yes_no),
+            arg!(mask: -m <mask> "-m <mask>: Event mask, any of (v)alue, (a)larm, (l)og, (p)roperty")
+                .default_value(DEFAULT_EVENT_MASK)
+                .value_parser(event_mask),
+            arg!(timestamp: --timestamp <kind>
+                 "Timestamp: (s)erver, (c)lient, (i)ncremental, (I)ncremental by channel, (r)elative, (n)one")
+                .value_parser(timestamp_kind),
+            arg!(stamp_digits: --"stamp-digits" <digits> "Fractional digits for timestamps: 0, 3, 6 or 9")
+                .value_parser(stamp_digits),
+            arg!(utc: --utc "Print timestamps in UTC, instead of local time"),
+            arg!(name_width: --"name-width" <n> "Pad the PV names to <n> characters")
+                .default_value(DEFAULT_NAME_WIDTH)
+                .value_parser(clap::value_parser!(usize)),
+            arg!(separator: -F <sep> "-F <sep>: Use <sep> as the output field separator")
+                .default_value(DEFAULT_SEPARATOR),
+            arg!(on_change: --"on-change" "Print an update only if the value differs from the previous one"),
+            arg!(interval: --interval <sec> "Print at most one update per PV every <sec> seconds, the latest one")
+                .value_parser(wait_time_in_range),
+            arg!(count: --count <n> "Exit after printing <n> updates")
+                .value_parser(count_in_range),
+            arg!(per_channel: --"per-channel" "With --count, print <n> updates of each PV before exiting")
+                .requires("count"),
+            arg!(monotonic: --monotonic "Drop updates whose timestamp isn't newer than the last one printed"),
+            arg!(progress: --progress "While connecting, print how many PVs are connected to stderr every second"),
+            arg!(first_n: --"first-n" <n> "Print only the first <n> updates of each PV, on exit")
+                .value_parser(count_in_range)
+                .conflicts_with("last_n"),
+            arg!(last_n: --"last-n" <n> "Print only the last <n> updates of each PV, on exit")
+                .value_parser(count_in_range),
+            arg!(numeric: -n "Print DBF_ENUM values as numbers"),
+            arg!(list: -l <file> "-l <file>: Read PV names from a file, one per line (- for stdin)"),
+            arg!(name_match: --match <regex> "Keep only the PV names that match <regex>, anywhere in the name")
+                .value_parser(name_pattern),
+            arg!(names: [PV] ... "PV names"),
+        ])
+        .get_matches_from(args);
+
+    ca_env::apply_overrides(
+        matches.get_one::<String>("addr_list"),
+        matches.get_one::<bool>("auto_addr").copied(),
+    );
+
+    let mut names: Vec<String> = matches
+        .get_many::<String>("names")
+        .map(|names| names.cloned().collect())
+        .unwrap_or_default();
+    if let Some(path) = matches.get_one::<String>("list") {
+        names.extend(read_pv_list(path)?);
+    }
+    if names.is_empty() {
+        return Err(UnifiedError::Misc("No PV names given".into()));
+    }
+    if let Some(pattern) = matches.get_one::<Regex>("name_match") {
+        names.retain(|name| pattern.is_match(name));
+        if names.is_empty() {
+            return Err(UnifiedError::Misc(format!("No PV names match '{pattern}'")));
+        }
+    }
+    let wait_time = *matches.get_one::<f32>("wait").unwrap();
+
+    Ok(Config {
+        names,
+        wait_time,
+        mask: *matches.get_one::<EventMask>("mask").unwrap(),
+        timestamp: matches
+            .get_one::<TimestampKind>("timestamp")
+            .copied()
+            .unwrap_or(DEFAULT_TIMESTAMP),
+        format: FormatOpts {
+            enum_as_number: matches.get_flag("numeric"),
+            stamp_digits: matches
+                .get_one::<usize>("stamp_digits")
+                .copied()
+                .unwrap_or(DEFAULT_STAMP_DIGITS),
+            utc: matches.get_flag("utc"),
+            separator: matches.get_one::<String>("separator").unwrap().clone(),
+            ..Default::default()
+        },
+        name_width: *matches.get_one::<usize>("name_width").unwrap(),
+        on_change: matches.get_flag("on_change"),
+        interval: matches.get_one::<f32>("interval").map(|&secs| Duration::from_secs_f32(secs)),
+        count: matches.get_one::<usize>("count").copied(),
+        per_channel: matches.get_flag("per_channel"),
+        monotonic: matches.get_flag("monotonic"),
+        progress: matches.get_flag("progress"),
+        sample: match (matches.get_one::<usize>("first_n"), matches.get_one::<usize>("last_n")) {
+            (Some(&n), _) => Some(Sample::First(n)),
+            (_, Some(&n)) => Some(Sample::Last(n)),
+            _ => None,
+        },
+    })
+}
+
+enum Event {
+    Update(Box<Info>),
+    Error(UnifiedError),
+    Disconnected(String),
+    Reconnected(String),
+}
+
+// Same layout as `rcaget -a`
+fn format_update(chan_info: &Info, config: &Config, stamps: &mut TimestampFormatter) -> String {
+    let mut components = vec![format!("{:<width$}", chan_info.name, width = config.name_width)];
+    if let Some(stamp) = stamps.format(chan_info, &config.format) {
+        components.push(stamp);
+    }
+    if chan_info.is_scalar() {
+        components.push(chan_info.format_scalar(&config.format));
+    } else {
+        components.push(format!("{}", chan_info.valid_elements));
+        // An empty array (NORD = 0) is printed as just its count
+        let value = chan_info.format_array_full(&config.format);
+        if !value.is_empty() {
+            components.push(value);
+        }
+    }
+    components.push(chan_info.format_alarm());
+
+    components.join(&config.format.separator)
+}
+
+// Errors go to stderr instead, but they're given a line too
+fn format_event(event: &Event, config: &Config, stamps: &mut TimestampFormatter) -> String {
+    match event {
+        Event::Update(info) => format_update(info, config, stamps),
+        Event::Error(e) => e.to_string(),
+        Event::Disconnected(name) => connection_change(name, "<disconnected>", config),
+        Event::Reconnected(name) => connection_change(name, "<reconnected>", config),
+    }
+}
+
+fn connection_change(name: &str, change: &str, config: &Config) -> String {
+    let name = format!("{name:<width$}", width = config.name_width);
+    [name.as_str(), change].join(&config.format.separator)
+}
+
+// With --on-change: whether `info` holds the same value as the last one printed for its PV
+fn repeats(last: &mut HashMap<String, Vec<ParsedValue>>, info: &Info) -> bool {
+    let elements = info.value.elements();
+    if last.get(&info.name).is_some_and(|prev| same_elements(prev, &elements)) {
+        return true;
+    }
+    last.insert(info.name.clone(), elements);
+    false
+}
+
+/*
+   Decides which updates get printed. With --on-change, values that repeat the last one printed
+   for the channel are skipped. With --interval, an update that comes too soon after the last one
+   printed is held back until the interval is over, replacing any other held for the channel.
+   Only printed updates restart the interval, and only they count towards --count. With
+   --monotonic, updates with a server timestamp not newer than the last one printed are dropped.
+   With --first-n or --last-n, the updates that would be printed are kept in a bounded buffer
+   per channel instead, and printed on exit.
+*/
+struct Printer<'a> {
+    config: &'a Config,
+    stamps: TimestampFormatter,
+    last: HashMap<String, Vec<ParsedValue>>,
+    // When each channel may print again, and its latest update held back until then
+    throttle: HashMap<String, (Instant, Option<Box<Info>>)>,
+    // Updates printed by each channel
+    printed: HashMap<String, usize>,
+    // Server timestamp of the last update printed, and out of order updates dropped, per channel
+    newest: HashMap<String, SystemTime>,
+    dropped: HashMap<String, usize>,
+    // Lines held for --first-n/--last-n, and the order in which the channels were first seen
+    samples: HashMap<String, VecDeque<String>>,
+    sampled: Vec<String>,
+    channels: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(config: &'a Config, channels: usize) -> Self {
+        Printer {
+            config,
+            stamps: TimestampFormatter::new(config.timestamp, &SystemClock),
+            last: HashMap::new(),
+            throttle: HashMap::new(),
+            printed: HashMap::new(),
+            newest: HashMap::new(),
+            dropped: HashMap::new(),
+            samples: HashMap::new(),
+            sampled: vec![],
+            channels,
+        }
+    }
+
+    // Whether --count has been reached
+    fn done(&self) -> bool {
+        match (self.config.count, self.config.per_channel) {
+            (None, _) => false,
+            (Some(count), false) => self.printed.values().sum::<usize>() >= count,
+            (Some(count), true) => {
+                self.printed.len() == self.channels && self.printed.values().all(|&n| n >= count)
+            }
+        }
+    }
+
+    fn update(&mut self, info: Box<Info>) {
+        if let Some((next, held)) = self.throttle.get_mut(&info.name) {
+            if Instant::now() < *next {
+                *held = Some(info);
+                return;
+            }
+        }
+        self.show(&info);
+    }
+
+    // The earliest time a held update is due
+    fn next_due(&self) -> Option<Instant> {
+        self.throttle
+            .values()
+            .filter(|(_, held)| held.is_some())
+            .map(|(next, _)| *next)
+            .min()
+    }
+
+    fn release_due(&mut self) {
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .throttle
+            .values_mut()
+            .filter(|(next, _)| *next <= now)
+            .filter_map(|(_, held)| held.take())
+            .collect();
+        for info in due {
+            self.show(&info);
+        }
+    }
+
+    // Prints whatever was held for the channel, and forgets about it
+    fn disconnected(&mut self, name: &str) {
+        if let Some((_, Some(info))) = self.throttle.remove(name) {
+            self.show(&info);
+        }
+        // `show` may have started a new interval
+        self.throttle.remove(name);
+        // The first value after reconnecting is always shown
+        self.last.remove(name);
+        let event = Event::Disconnected(name.to_string());
+        println!("{}", format_event(&event, self.config, &mut self.stamps));
+    }
+
+    fn report_dropped(&self) {
+        let mut dropped: Vec<_> = self.dropped.iter().collect();
+        dropped.sort();
+        for (name, count) in dropped {
+            eprintln!("{name}: {count} out of order update(s) dropped");
+        }
+    }
+
+    fn emit(&mut self, name: &str, line: String) {
+        let Some(sample) = self.config.sample else {
+            println!("{line}");
+            return;
+        };
+        if !self.samples.contains_key(name) {
+            self.sampled.push(name.to_string());
+        }
+        let buffer = self.samples.entry(name.to_string()).or_default();
+        match sample {
+            Sample::First(n) if buffer.len() < n => buffer.push_back(line),
+            Sample::First(_) => (),
+            Sample::Last(n) => {
+                if buffer.len() == n {
+                    buffer.pop_front();
+                }
+                buffer.push_back(line);
+            }
+        }
+    }
+
+    // Prints the lines held for --first-n/--last-n, grouped by channel
+    fn flush_samples(&mut self) {
+        for name in self.sampled.drain(..) {
+            for line in self.samples.remove(&name).unwrap_or_default() {
+                println!("{line}");
+            }
+        }
+    }
+
+    fn show(&mut self, info: &Info) {
+        let printed = self.printed.get(&info.name).copied().unwrap_or(0);
+        // Channels that are through with --count --per-channel wait for the others quietly
+        if self.done() || (self.config.per_channel && self.config.count.is_some_and(|n| printed >= n)) {
+            return;
+        }
+        let stamp = info.value.get_stamp().to_system();
+        if self.config.monotonic && self.newest.get(&info.name).is_some_and(|&newest| stamp <= newest) {
+            *self.dropped.entry(info.name.clone()).or_default() += 1;
+            return;
+        }
+        if self.config.on_change && repeats(&mut self.last, info) {
+            return;
+        }
+        let line = format_update(info, self.config, &mut self.stamps);
+        self.emit(&info.name, line);
+        self.printed.insert(info.name.clone(), printed + 1);
+        self.newest.insert(info.name.clone(), stamp);
+        if let Some(interval) = self.config.interval {
+            self.throttle.insert(info.name.clone(), (Instant::now() + interval, None));
+        }
+    }
+}
+
+async fn run(config: Config) -> UnifiedResult<()> {
+    let interrupted = install_ctrl_c();
+    let timeout = (config.wait_time * 1000.0) as u64;
+    let ctx = build_context(&CaEnv::from_env())?;
+    let (mut channels, failed) = get_channels(&ctx, &config.names)?;
+    report_failed(&failed);
+    wait_connect_with_progress(&mut channels, timeout, config.progress).await?;
+
+    // A PV given twice is still a single channel for --per-channel
+    let distinct: HashSet<_> = channels.iter().map(|ch| ch.name().to_owned()).collect();
+    let mut printer = Printer::new(&config, distinct.len());
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut set = JoinSet::new();
+
+    for mut ch in channels {
+        let tx = tx.clone();
+        let mask = config.mask;
+        let name = ch.name().to_string_lossy().to_string();
+        set.spawn(async move {
+            /*
+               `monitor` returns on disconnection. Subscribe again once the IOC is back. If the
+               channel is still connected, something else went wrong: an error won't go away by
+               subscribing again, and a subscription that just ended is retried after a pause.
+            */
+            loop {
+                let res = monitor(&mut ch, mask, |update| {
+                    let _ = tx.send(match update {
+                        Ok(info) => Event::Update(Box::new(info)),
+                        Err(e) => Event::Error(e),
+                    });
+                })
+                .await;
+                if connection_state(&ch) == ConnectionState::Connected {
+                    match res {
+                        Err(e) => {
+                            let _ = tx.send(Event::Error(e));
+                            break;
+                        }
+                        Ok(()) => {
+                            sleep(Duration::from_millis(RETRY_BACKOFF_MS)).await;
+                            continue;
+                        }
+                    }
+                }
+                if let Err(e) = res {
+                    let _ = tx.send(Event::Error(e));
+                }
+                let _ = tx.send(Event::Disconnected(name.clone()));
+                ch.connected().await;
+                let _ = tx.send(Event::Reconnected(name.clone()));
+            }
+        });
+    }
+
+    tokio::pin!(interrupted);
+    loop {
+        let due = printer.next_due();
+        select! {
+            Some(event) = rx.recv() => match event {
+                Event::Update(info) => printer.update(info),
+                Event::Error(e) => eprintln!("{e}"),
+                Event::Disconnected(name) => printer.disconnected(&name),
+                event => println!("{}", format_event(&event, &config, &mut printer.stamps)),
+            },
+            // The expression is evaluated even when the branch is disabled
+            () = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => printer.release_due(),
+            () = &mut interrupted => break,
+        }
+        if printer.done() {
+            break;
+        }
+    }
+
+    printer.flush_samples();
+    let _ = io::stdout().flush();
+    printer.report_dropped();
+    if printer.done() {
+        Ok(())
+    } else {
+        Err(UnifiedError::Interrupted)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match get_arguments().and_then(run).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // Ctrl-C is the normal way out, so it's reflected only in the exit code
+        Err(e @ UnifiedError::Interrupted) => ExitCode::from(e.exit_code() as u8),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code() as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epics_tools::{test_support::{time_array, time_value}, types::RawValue};
+
+    fn config(args: &[&str]) -> Config {
+        parse_arguments(["rcamonitor"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn updates_look_like_rcaget_wide() {
+        let config = config(&["pv"]);
+        let scalar = Info::new("pv".into(), 1, RawValue::Long(time_value(3)));
+        let stamp = scalar.format_stamp(&config.format);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        assert_eq!(format_update(&scalar, &config, &mut stamps), format!("{:<30} {stamp} 3 NO_ALARM NO_ALARM", "pv"));
+        let array = Info::new("pv".into(), 2, RawValue::LongArray(time_array(&[1, 2])));
+        assert_eq!(format_update(&array, &config, &mut stamps), format!("{:<30} {stamp} 2 1 2 NO_ALARM NO_ALARM", "pv"));
+    }
+
+    #[test]
+    fn updates_follow_the_layout_options() {
+        let config = config(&["--name-width=0", "-F", ",", "--timestamp", "n", "pv"]);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let array = Info::new("pv".into(), 2, RawValue::LongArray(time_array(&[1, 2])));
+        assert_eq!(format_update(&array, &config, &mut stamps), "pv,2,1,2,NO_ALARM NO_ALARM");
+        // No empty value for an array with no elements (NORD = 0)
+        let mut empty = Info::new("pv".into(), 2, RawValue::LongArray(time_array::<i32>(&[])));
+        empty.valid_elements = 0;
+        assert_eq!(format_update(&empty, &config, &mut stamps), "pv,0,NO_ALARM NO_ALARM");
+        let down = format_event(&Event::Disconnected("pv".into()), &config, &mut stamps);
+        assert_eq!(down, "pv,<disconnected>");
+    }
+
+    #[test]
+    fn incremental_stamps_by_channel() {
+        let config = config(&["--timestamp", "I", "a", "b"]);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let sample = |name: &str, sec| {
+            let mut value = time_value(0);
+            value.stamp.0.secPastEpoch = sec;
+            Info::new(name.into(), 1, RawValue::Long(value))
+        };
+        let lines: Vec<_> = [sample("a", 10), sample("b", 11), sample("a", 15)]
+            .iter()
+            .map(|info| format_update(info, &config, &mut stamps))
+            .collect();
+        assert_eq!(lines[1], format!("{:<30} 0.000000 0 NO_ALARM NO_ALARM", "b"));
+        assert_eq!(lines[2], format!("{:<30} 5.000000 0 NO_ALARM NO_ALARM", "a"));
+    }
+
+    #[test]
+    fn connection_changes_are_shown_in_the_value_column() {
+        let config = config(&["pv"]);
+        let mut stamps = TimestampFormatter::new(config.timestamp, &SystemClock);
+        let down = format_event(&Event::Disconnected("pv".into()), &config, &mut stamps);
+        assert_eq!(down, format!("{:<30} <disconnected>", "pv"));
+        let up = format_event(&Event::Reconnected("pv".into()), &config, &mut stamps);
+        assert_eq!(up, format!("{:<30} <reconnected>", "pv"));
+        let update = Event::Update(Box::new(Info::new("pv".into(), 1, RawValue::Long(time_value(3)))));
+        assert!(format_event(&update, &config, &mut stamps).ends_with(" 3 NO_ALARM NO_ALARM"));
+    }
+
+    #[test]
+    fn on_change_skips_repeated_values() {
+        let long = |name: &str, value: i32| Info::new(name.into(), 1, RawValue::Long(time_value(value)));
+        let mut last = HashMap::new();
+        let repeated: Vec<_> = [("a", 1), ("a", 1), ("b", 1), ("a", 2), ("a", 2), ("a", 1)]
+            .iter()
+            .map(|&(name, value)| repeats(&mut last, &long(name, value)))
+            .collect();
+        assert_eq!(repeated, [false, true, false, false, true, false]);
+        // Arrays repeat only if every element does
+        let array = |values: &[i32]| Info::new("c".into(), 3, RawValue::LongArray(time_array(values)));
+        assert!(!repeats(&mut last, &array(&[1, 2])));
+        assert!(repeats(&mut last, &array(&[1, 2])));
+        assert!(!repeats(&mut last, &array(&[1, 2, 3])));
+    }
+
+    #[test]
+    fn updates_within_the_interval_are_held_back() {
+        let config = config(&["--interval", "0.05", "pv"]);
+        let long = |value: i32| Box::new(Info::new("pv".into(), 1, RawValue::Long(time_value(value))));
+        let held = |printer: &Printer| match &printer.throttle["pv"].1 {
+            Some(info) => info.format_scalar(&FormatOpts::default()),
+            None => "-".into(),
+        };
+        let mut printer = Printer::new(&config, 1);
+        printer.update(long(1));
+        assert!(printer.next_due().is_none());
+        // Only the latest one is kept
+        printer.update(long(2));
+        printer.update(long(3));
+        assert_eq!(held(&printer), "3");
+        let due = printer.next_due().unwrap();
+        printer.release_due();
+        assert_eq!(held(&printer), "3");
+        std::thread::sleep(due - Instant::now());
+        printer.release_due();
+        assert_eq!(held(&printer), "-");
+        assert!(printer.next_due().is_none());
+    }
+
+    fn long(name: &str, value: i32) -> Box<Info> {
+        Box::new(Info::new(name.into(), 1, RawValue::Long(time_value(value))))
+    }
+
+    #[test]
+    fn count_in_total_or_per_channel() {
+        let total = config(&["--count", "2", "a", "b"]);
+        let mut printer = Printer::new(&total, 2);
+        printer.update(long("a", 1));
+        assert!(!printer.done());
+        printer.update(long("a", 2));
+        assert!(printer.done());
+
+        let per_channel = config(&["--count", "2", "--per-channel", "a", "b"]);
+        let mut printer = Printer::new(&per_channel, 2);
+        for value in 0..3 {
+            printer.update(long("a", value));
+        }
+        // The extra update isn't printed, while b catches up
+        assert_eq!(printer.printed["a"], 2);
+        assert!(!printer.done());
+        printer.update(long("b", 1));
+        printer.update(long("b", 2));
+        assert!(printer.done());
+    }
+
+    #[test]
+    fn the_first_value_after_reconnecting_is_shown() {
+        let config = config(&["--on-change", "pv"]);
+        let mut printer = Printer::new(&config, 1);
+        printer.update(long("pv", 1));
+        printer.update(long("pv", 1));
+        assert_eq!(printer.printed["pv"], 1);
+        printer.disconnected("pv");
+        printer.update(long("pv", 1));
+        assert_eq!(printer.printed["pv"], 2);
+    }
+
+    #[test]
+    fn monotonic_drops_the_stale_updates() {
+        let config = config(&["--monotonic", "pv"]);
+        let mut printer = Printer::new(&config, 1);
+        let stamped = |sec| {
+            let mut info = long("pv", 0);
+            if let RawValue::Long(value) = &mut info.value {
+                value.stamp.0.secPastEpoch = sec;
+            }
+            info
+        };
+        for sec in [10, 12, 11, 12, 13] {
+            printer.update(stamped(sec));
+        }
+        assert_eq!((printer.printed["pv"], printer.dropped["pv"]), (3, 2));
+    }
+
+    // The values held for each channel, in the order the channels were first seen
+    fn sampled(args: &[&str]) -> Vec<(String, Vec<String>)> {
+        let config = config(&[&["--timestamp", "n"], args].concat());
+        let mut printer = Printer::new(&config, 2);
+        for (name, value) in [("b", 1), ("a", 1), ("b", 2), ("b", 3)] {
+            printer.update(long(name, value));
+        }
+        let value = |line: &String| line.split_whitespace().nth(1).unwrap().to_string();
+        let names = printer.sampled.clone();
+        names.into_iter().map(|name| (name.clone(), printer.samples[&name].iter().map(value).collect())).collect()
+    }
+
+    #[test]
+    fn samples_of_the_first_or_last_updates() {
+        let first = sampled(&["--first-n", "2", "a", "b"]);
+        assert_eq!(first, [("b".to_string(), vec!["1".to_string(), "2".to_string()]), ("a".to_string(), vec!["1".to_string()])]);
+        let last = sampled(&["--last-n", "2", "a", "b"]);
+        assert_eq!(last[0].1, ["2", "3"]);
+    }
+}
@@ -0,0 +1,234 @@
+use epics_tools::{build_context, wait_connect, get_channels, grab_info, put_values};
+use std::process::ExitCode;
+
+use std::ffi::OsString;
+
+use clap::{arg, Command};
+use epics_ca::types::FieldId;
+use epics_tools::{
+    config::{
+        ca_env::{self, CaEnv},
+        DEFAULT_CSV_SEPARATOR, DEFAULT_WAIT_TIME,
+        tolerance, wait_time_in_range
+    },
+    types::{parse_value, truncate_epics_string, verify_written, FormatOpts, Info},
+    UnifiedError,
+    UnifiedResult
+};
+
+use futures::TryFutureExt;
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+struct Config {
+    name: String,
+    values: Vec<String>,
+    wait_time: f32,
+    format: FormatOpts,
+    // Flags
+    terse: bool,
+    truncate: bool,
+    // Check that the value read back after the write is the one written
+    verify: bool,
+    tolerance: f64,
+}
+
+async fn get_arguments() -> UnifiedResult<Config> {
+    parse_arguments(std::env::args_os())
+}
+
+fn parse_arguments<I, T>(args: I) -> UnifiedResult<Config>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = Command::new(PKG_NAME)
+        .version(PKG_VERSION)
+        .author(PKG_AUTHORS)
+        .about("Rust caput")
+        .args([
+            arg!(wait: -w <sec> "-w <sec>: Wait time, specifies CA timeout")
+                .default_value(DEFAULT_WAIT_TIME)
+                .value_parser(wait_time_in_range),
+            arg!(addr_list: --"addr-list" <addrs> "Override EPICS_CA_ADDR_LIST"),
+            arg!(auto_addr: --"auto-addr" <yes_no> "Override EPICS_CA_AUTO_ADDR_LIST")
+                .value_parser(ca_env::yes_no),
+            arg!(terse: -t "Terse mode - print only successfully written value, without name"),
+            arg!(numeric: -n "Print DBF_ENUM values as numbers"),
+            arg!(array: -a --array "Write a single argument as an array, splitting it at the separator"),
+            arg!(separator: -F <sep> "-F <sep>: Separator for the array elements given with -a (default: ,)")
+                .requires("array"),
+            arg!(verify: --verify "Read the value back after writing it, and fail if it differs"),
+            arg!(tolerance: --tolerance <eps> "With --verify, accept floats within <eps> of the written value")
+                .requires("verify")
+                .value_parser(tolerance),
+            arg!(truncate: --truncate "Truncate values too long for a DBF_STRING, instead of failing"),
+            arg!(prefix: --prefix <str> "Prepend <str> to the PV name"),
+            arg!(name: <PV> "PV name"),
+            arg!(values: <value> ... "Value(s) to be written"),
+        ])
+        .get_matches_from(args);
+
+    ca_env::apply_overrides(
+        matches.get_one::<String>("addr_list"),
+        matches.get_one::<bool>("auto_addr").copied(),
+    );
+
+    let mut values: Vec<String> = matches
+        .get_many::<String>("values")
+        .unwrap()
+        .cloned()
+        .collect();
+    // Like caput -a, but the elements come in a single argument, as in "1,2,3"
+    if matches.get_flag("array") {
+        if values.len() != 1 {
+            return Err(UnifiedError::Misc("-a takes the whole array as a single argument".into()));
+        }
+        let sep = matches
+            .get_one::<String>("separator")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_CSV_SEPARATOR);
+        if sep.is_empty() {
+            return Err(UnifiedError::Misc("The array separator can't be empty".into()));
+        }
+        values = values[0].split(sep).map(String::from).collect();
+    }
+    let wait_time = *matches.get_one::<f32>("wait").unwrap();
+
+    Ok(Config {
+        name: format!(
+            "{}{}",
+            matches.get_one::<String>("prefix").map(String::as_str).unwrap_or_default(),
+            matches.get_one::<String>("name").unwrap()
+        ),
+        values,
+        wait_time,
+        terse: matches.get_flag("terse"),
+        truncate: matches.get_flag("truncate"),
+        verify: matches.get_flag("verify"),
+        tolerance: matches.get_one::<f64>("tolerance").copied().unwrap_or(0.0),
+        format: FormatOpts {
+            enum_as_number: matches.get_flag("numeric"),
+            ..Default::default()
+        },
+    })
+}
+
+fn format_info(chan_info: &Info, config: &Config) -> String {
+    if chan_info.is_scalar() {
+        format!("{:<30} {}", chan_info.name, chan_info.format_scalar(&config.format))
+    } else {
+        format!(
+            "{} {} {}",
+            chan_info.name,
+            chan_info.valid_elements,
+            chan_info.format_array_full(&config.format)
+        )
+    }
+}
+
+async fn run(config: Config) -> UnifiedResult<()> {
+    let timeout = (config.wait_time * 1000.0) as u64;
+    let ctx = build_context(&CaEnv::from_env())?;
+    let (mut channels, mut failed) = get_channels(&ctx, std::slice::from_ref(&config.name))?;
+    if let Some((_, error)) = failed.pop() {
+        return Err(error);
+    }
+    wait_connect(&mut channels, timeout).await?;
+    let mut channel = channels.pop().unwrap();
+
+    let old = grab_info(&mut channel).await?;
+    let values: Vec<String> = if config.truncate && old.value.field_id() == FieldId::String {
+        config
+            .values
+            .iter()
+            .map(|v| {
+                let short = truncate_epics_string(v);
+                if short.len() < v.len() {
+                    eprintln!("Warning: '{v}' truncated to '{short}'");
+                }
+                short.to_string()
+            })
+            .collect()
+    } else {
+        config.values.clone()
+    };
+    put_values(&mut channel, &values).await?;
+    let new = grab_info(&mut channel).await?;
+
+    if config.terse {
+        println!("{}", if new.is_scalar() {
+            new.format_scalar(&config.format)
+        } else {
+            new.format_array_full(&config.format)
+        });
+    } else {
+        println!("Old : {}", format_info(&old, &config));
+        println!("New : {}", format_info(&new, &config));
+    }
+
+    if config.verify {
+        // Parsed as the write did, so that enum labels compare by their index
+        let tp = new.value.field_id();
+        let written = values
+            .iter()
+            .map(|v| parse_value(tp, v, &new.enum_strings))
+            .collect::<UnifiedResult<Vec<_>>>()?;
+        if !verify_written(&new.value, &written, config.tolerance) {
+            return Err(UnifiedError::Misc(format!(
+                "Warning: {} reads back a different value than the one written",
+                new.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match get_arguments().and_then(run).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code() as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epics_tools::{test_support::{time_array, time_value}, types::RawValue};
+
+    fn config(args: &[&str]) -> Config {
+        parse_arguments(["rcaput"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn old_and_new_values() {
+        let config = config(&["pv", "1"]);
+        let scalar = Info::new("pv".into(), 1, RawValue::Long(time_value(1)));
+        assert_eq!(format_info(&scalar, &config), format!("{:<30} 1", "pv"));
+        let array = Info::new("pv".into(), 2, RawValue::LongArray(time_array(&[1, 2])));
+        assert_eq!(format_info(&array, &config), "pv 2 1 2");
+    }
+
+    #[test]
+    fn arrays_in_a_single_argument() {
+        assert_eq!(config(&["-a", "pv", "1,2,3"]).values, ["1", "2", "3"]);
+        assert_eq!(config(&["-a", "-F", ";", "pv", "1;2"]).values, ["1", "2"]);
+        assert_eq!(config(&["pv", "1,2"]).values, ["1,2"]);
+        let res = parse_arguments(["rcaput", "-a", "pv", "1", "2"]);
+        assert!(matches!(res, Err(UnifiedError::Misc(msg)) if msg.starts_with("-a takes the whole array")));
+        assert!(parse_arguments(["rcaput", "-a", "-F", "", "pv", "1"]).is_err());
+    }
+
+    #[test]
+    fn prefix_for_the_name() {
+        assert_eq!(config(&["--prefix", "DEV:", "pv", "1"]).name, "DEV:pv");
+        assert_eq!(config(&["pv", "1"]).name, "pv");
+    }
+}
@@ -0,0 +1,154 @@
+use epics_tools::{build_context, get_channels, install_ctrl_c, monitor};
+use std::{process::ExitCode, time::Duration};
+
+use clap::{arg, Command};
+use epics_ca::types::EventMask;
+use epics_tools::{
+    config::{ca_env::{self, CaEnv}, comparison_op, wait_time_in_range},
+    types::{matches, FormatOpts, Info, Op, RawValue},
+    UnifiedError,
+    UnifiedResult
+};
+
+use futures::{future::{pending, Either}, TryFutureExt};
+use tokio::{select, sync::mpsc, time::sleep};
+
+const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+struct Config {
+    name: String,
+    op: Op,
+    target: String,
+    // No timeout means waiting forever
+    wait_time: Option<f32>,
+}
+
+async fn get_arguments() -> UnifiedResult<Config> {
+    let matches = Command::new(PKG_NAME)
+        .version(PKG_VERSION)
+        .author(PKG_AUTHORS)
+        .about("Rust cawait")
+        .args([
+            arg!(wait: -w <sec> "-w <sec>: Give up after <sec> seconds (default: wait forever)")
+                .value_parser(wait_time_in_range),
+            arg!(addr_list: --"addr-list" <addrs> "Override EPICS_CA_ADDR_LIST"),
+            arg!(auto_addr: --"auto-addr" <yes_no> "Override EPICS_CA_AUTO_ADDR_LIST")
+                .value_parser(ca_env::yes_no),
+            arg!(name: <PV> "PV name"),
+            arg!(op: <op> "Comparison: == != > >= < <=").value_parser(comparison_op),
+            arg!(target: <value> "Value to compare against"),
+        ])
+        .get_matches();
+
+    ca_env::apply_overrides(
+        matches.get_one::<String>("addr_list"),
+        matches.get_one::<bool>("auto_addr").copied(),
+    );
+
+    Ok(Config {
+        name: matches.get_one::<String>("name").unwrap().clone(),
+        op: *matches.get_one::<Op>("op").unwrap(),
+        target: matches.get_one::<String>("target").unwrap().clone(),
+        wait_time: matches.get_one::<f32>("wait").copied(),
+    })
+}
+
+// Enum targets can be given as labels. `matches` only knows about indices
+fn resolve_target(info: &Info, target: &str) -> String {
+    match info.value {
+        RawValue::Enum(_) => info
+            .enum_strings
+            .iter()
+            .position(|label| label == target)
+            .map(|idx| idx.to_string())
+            .unwrap_or_else(|| target.into()),
+        _ => target.into(),
+    }
+}
+
+// Returns the update that satisfied the condition
+async fn wait_for(config: &Config) -> UnifiedResult<Info> {
+    let ctx = build_context(&CaEnv::from_env())?;
+    let (mut channels, mut failed) = get_channels(&ctx, std::slice::from_ref(&config.name))?;
+    if let Some((_, error)) = failed.pop() {
+        return Err(error);
+    }
+    let mut channel = channels.pop().unwrap();
+
+    // `monitor` returns when the channel disconnects: keep waiting across IOC restarts
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            channel.connected().await;
+            if let Err(e) = monitor(&mut channel, EventMask::VALUE | EventMask::ALARM, |update| {
+                let _ = tx.send(update);
+            })
+            .await
+            {
+                let _ = tx.send(Err(e));
+                break;
+            }
+        }
+    });
+
+    while let Some(update) = rx.recv().await {
+        let info = update?;
+        if !info.is_scalar() {
+            return Err(UnifiedError::Misc(format!("{} is not a scalar", config.name)));
+        }
+        if matches(&info.value, config.op, &resolve_target(&info, &config.target)) {
+            return Ok(info);
+        }
+    }
+
+    Err(UnifiedError::Misc(format!("{}: the monitor stopped", config.name)))
+}
+
+async fn run(config: Config) -> UnifiedResult<()> {
+    let deadline = match config.wait_time {
+        Some(secs) => Either::Left(sleep(Duration::from_secs_f32(secs))),
+        None => Either::Right(pending()),
+    };
+
+    select! {
+        res = wait_for(&config) => {
+            let info = res?;
+            println!("{:<30} {}", info.name, info.format_scalar(&FormatOpts::default()));
+            Ok(())
+        }
+        () = deadline => Err(UnifiedError::ConditionTimeout),
+        () = install_ctrl_c() => Err(UnifiedError::Interrupted),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match get_arguments().and_then(run).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // Ctrl-C is the normal way out, so it's reflected only in the exit code
+        Err(e @ UnifiedError::Interrupted) => ExitCode::from(e.exit_code() as u8),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code() as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epics_ca::types::EpicsEnum;
+    use epics_tools::test_support::time_value;
+
+    #[test]
+    fn enum_targets_may_be_labels() {
+        let mut info = Info::new("pv".into(), 1, RawValue::Enum(time_value(EpicsEnum(1))));
+        info.enum_strings = vec!["Closed".into(), "Open".into()];
+        assert_eq!(resolve_target(&info, "Open"), "1");
+        assert_eq!(resolve_target(&info, "0"), "0");
+        assert!(matches(&info.value, Op::Eq, &resolve_target(&info, "Open")));
+        assert!(!matches(&info.value, Op::Eq, &resolve_target(&info, "Closed")));
+    }
+}
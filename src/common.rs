@@ -1,100 +1,1077 @@
 use crate::types::RawValue;
-use std::{ffi::CString, time::Duration};
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    fs,
+    io,
+    marker::PhantomData,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
 
 use epics_ca::{
     Context,
     Channel,
-    request,
-    types::{EpicsEnum, EpicsString, FieldId}
+    channel::{get::Callback, subscribe::Queue},
+    error,
+    request::{self, ReadRequest, Request},
+    types::{EpicsEnum, EpicsString, EventMask, Field, FieldId, RequestId}
+};
+use epics_ca_sys as sys;
+use futures::{future::{join_all, BoxFuture}, stream, Future, FutureExt, Stream, StreamExt};
+use tokio::{signal, sync::{mpsc, oneshot}, time::{interval, interval_at, sleep, Instant}, select};
+
+use crate::{
+    UnifiedResult,
+    UnifiedError,
+    config::{ca_env::{self, CaEnv}, pv_field, validate_pv_name, CONNECTION_POLL_MS, PROGRESS_INTERVAL_MS, RETRY_BACKOFF_MS},
+    types::{field_type_name, parse_value, ChannelInfo, ConnectionState, Info, Limits, ParsedValue},
 };
-use futures::future::join_all;
-use tokio::{time::sleep, select};
 
-use crate::{UnifiedResult, UnifiedError, types::Info};
 
+pub type FailedChannels = Vec<(String, UnifiedError)>;
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/*
+   Diagnostics, printed only if the verbosity is at least `level`. They always go to stderr, so
+   that they can't mix with the values when the output is piped.
+*/
+pub fn log(level: u8, message: impl std::fmt::Display) {
+    if log_enabled(level) {
+        eprintln!("[{level}] {message}");
+    }
+}
+
+// Whether `log` prints at `level`, for diagnostics that take some work to put together
+pub fn log_enabled(level: u8) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level
+}
 
-pub fn get_channels(ctx: &Context, names: &[String]) -> UnifiedResult<Vec<Channel>> {
+/*
+   Returns the channels that could be created, along with the names that failed and why.
+   Both lists keep the same relative order as the input names.
+*/
+pub fn get_channels(
+    ctx: &Context,
+    names: &[String],
+) -> UnifiedResult<(Vec<Channel>, FailedChannels)> {
     let mut errors = vec![];
 
     let channels: Vec<_> = names
         .iter()
-        .map(|name| match CString::new(name.as_str()) {
-            Ok(pvname) => Channel::new(ctx, &pvname).map_err(UnifiedError::CaError),
-            Err(error) => Err(UnifiedError::Misc(format!("{error}"))),
-        })
-        .filter_map(|r| r.map_err(|e| errors.push(e)).ok())
+        .map(|name| Ok(Channel::new(ctx, &channel_name(name)?)?))
+        .zip(names)
+        .filter_map(|(r, name)| r.map_err(|e| errors.push((name.clone(), e))).ok())
         .collect();
 
-    Ok(channels)
+    Ok((channels, errors))
+}
+
+// The name as libca takes it, once it's known to be valid
+fn channel_name(name: &str) -> UnifiedResult<CString> {
+    validate_pv_name(name).map_err(UnifiedError::Misc)?;
+    Ok(CString::new(name)?)
+}
+
+/*
+   A PV name may carry its own connection timeout in seconds, as in "PV@2.5". Anything after the
+   last '@' that isn't a positive number is considered part of the name.
+*/
+pub fn parse_pv_spec(spec: &str) -> (String, Option<f32>) {
+    if let Some((name, timeout)) = spec.rsplit_once('@') {
+        if let Ok(secs) = timeout.parse::<f32>() {
+            if secs > 0.0 && !name.is_empty() {
+                return (name.to_string(), Some(secs));
+            }
+        }
+    }
+    (spec.to_string(), None)
+}
+
+// One PV name per line. Blank lines and comments (starting with '#') are skipped
+pub fn parse_pv_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+// Prepends a common prefix (e.g. the device, as in "DEV:") to every name
+pub fn add_prefix(names: &mut [String], prefix: &str) {
+    for name in names.iter_mut() {
+        name.insert_str(0, prefix);
+    }
+}
+
+// Reads a list of PV names from a file, or from stdin if `path` is "-"
+pub fn read_pv_list(path: &str) -> UnifiedResult<Vec<String>> {
+    let text = if path == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|error| UnifiedError::Misc(format!("Can't read the PV list from {path}: {error}")))?;
+
+    Ok(parse_pv_list(&text))
+}
+
+// Creates a context that uses the settings in `cfg`, on top of the current environment
+pub fn build_context(cfg: &CaEnv) -> UnifiedResult<Context> {
+    cfg.apply();
+    Ok(Context::new()?)
+}
+
+pub fn report_failed(failed: &[(String, UnifiedError)]) {
+    for (name, error) in failed {
+        eprintln!("{name}: {error}");
+    }
 }
 
 pub async fn wait_connect(channels: &mut [Channel], timeout: u64) -> UnifiedResult<()> {
-    let connected: Vec<_> = channels.iter_mut().map(|ch| ch.connected()).collect();
-    let sleeper = sleep(Duration::from_millis(timeout));
+    wait_connect_with_progress(channels, timeout, false).await
+}
+
+/*
+   Like wait_connect but, if `progress` is set, prints how many of the channels are connected to
+   stderr every PROGRESS_INTERVAL_MS while waiting.
+*/
+pub async fn wait_connect_with_progress(
+    channels: &mut [Channel],
+    timeout: u64,
+    progress: bool,
+) -> UnifiedResult<()> {
+    // Channels reused across reads (e.g. rcaget --poll) are usually connected already
+    if channels.iter().all(|ch| connection_state(ch) == ConnectionState::Connected) {
+        return Ok(());
+    }
+
+    // The future below borrows the channels, so take their IDs now to poll their state
+    let ids: Vec<_> = channels.iter().map(|ch| ChannelId(ch.raw())).collect();
+    let connected = join_all(channels.iter_mut().map(|ch| ch.connected()));
+    let period = progress.then(|| Duration::from_millis(PROGRESS_INTERVAL_MS));
+    let timed_out = race_with_ticker(connected, Duration::from_millis(timeout), period, || {
+        let done = ids
+            .iter()
+            .filter(|id| raw_connection_state(id) == ConnectionState::Connected)
+            .count();
+        eprintln!("connected {done}/{}", ids.len());
+    })
+    .await;
+    if !timed_out {
+        return Ok(());
+    }
+
+    let missing: Vec<_> = channels
+        .iter()
+        .filter(|ch| connection_state(ch) != ConnectionState::Connected)
+        .map(|ch| ch.name().to_string_lossy().to_string())
+        .collect();
+    connect_timeout(missing)
+}
+
+/*
+   Waits for `fut` for up to `timeout`, calling `tick` every `period` (if given) meanwhile.
+   Returns whether it timed out.
+*/
+async fn race_with_ticker(
+    fut: impl Future,
+    timeout: Duration,
+    period: Option<Duration>,
+    mut tick: impl FnMut(),
+) -> bool {
+    let sleeper = sleep(timeout);
+    // Without a period, the ticker is there but never polled
+    let every = period.unwrap_or(Duration::from_millis(PROGRESS_INTERVAL_MS));
+    let mut ticker = interval_at(Instant::now() + every, every);
 
     /*
        This is a bit of Rust's async black magic (pinned vs. unpinned data), having to
        do with data migration across threads. It makes sense once you read about it,
        though.
     */
-    tokio::pin!(sleeper);
+    tokio::pin!(fut, sleeper);
+
+    loop {
+        select! {
+            _ = &mut fut => break false,
+            () = &mut sleeper => break true,
+            _ = ticker.tick(), if period.is_some() => tick(),
+        }
+    }
+}
 
-    select! {
-        _ = join_all(connected) => Ok(()),
-        () = &mut sleeper =>
-            Err(UnifiedError::Misc("Channel connect timed out: some PV(s) not found.".into())),
+// The error for the channels still missing after the timeout. They may have all made it right
+// as the time ran out
+fn connect_timeout(missing: Vec<String>) -> UnifiedResult<()> {
+    if missing.is_empty() {
+        return Ok(());
     }
+    Err(UnifiedError::ConnectTimeout(missing))
+}
+
+/*
+   libca refuses values larger than EPICS_CA_MAX_ARRAY_BYTES with an error that doesn't say
+   why, so check it before asking.
+*/
+fn check_array_bytes(channel: &Channel, tp: FieldId) -> UnifiedResult<()> {
+    let limit = CaEnv::from_env().max_array_bytes();
+    match value_bytes(channel, Some(tp)) {
+        Some(bytes) if bytes > limit => Err(UnifiedError::Misc(format!(
+            "{} needs {bytes} bytes, more than {} ({limit}): raise it to read this PV",
+            channel.name().to_string_lossy(),
+            ca_env::MAX_ARRAY_BYTES,
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/*
+   Size of the channel's whole value, to compare against EPICS_CA_MAX_ARRAY_BYTES. It depends on
+   the type it's read as (`requested`, or the native one): as strings, every element takes 40 bytes.
+*/
+pub fn value_bytes(channel: &Channel, requested: Option<FieldId>) -> Option<usize> {
+    let tp = match requested {
+        Some(tp) => tp,
+        None => channel.field_type().ok()?,
+    };
+    Some(element_bytes(tp) * channel.element_count().ok()?)
+}
+
+fn element_bytes(tp: FieldId) -> usize {
+    match tp {
+        FieldId::Char => std::mem::size_of::<u8>(),
+        FieldId::Short => std::mem::size_of::<i16>(),
+        FieldId::Enum => std::mem::size_of::<EpicsEnum>(),
+        FieldId::Long => std::mem::size_of::<i32>(),
+        FieldId::Float => std::mem::size_of::<f32>(),
+        FieldId::Double => std::mem::size_of::<f64>(),
+        FieldId::String => std::mem::size_of::<EpicsString>(),
+    }
+}
+
+/*
+   Waits for a single channel, trying again up to `retries` times if it doesn't connect in time.
+   The pause between attempts doubles each time, starting at RETRY_BACKOFF_MS.
+*/
+pub async fn connect_with_retry(
+    channel: &mut Channel,
+    timeout: u64,
+    retries: u32,
+) -> UnifiedResult<()> {
+    with_retries(channel, retries, |channel| {
+        wait_connect(std::slice::from_mut(channel), timeout).boxed()
+    })
+    .await
+}
+
+/*
+   Runs `attempt` on `target` until it succeeds, trying again up to `retries` times. The pause
+   between attempts doubles each time, starting at RETRY_BACKOFF_MS.
+*/
+pub async fn with_retries<S, T, F>(target: &mut S, retries: u32, mut attempt: F) -> UnifiedResult<T>
+where
+    S: ?Sized,
+    F: for<'a> FnMut(&'a mut S) -> BoxFuture<'a, UnifiedResult<T>>,
+{
+    let mut backoff = RETRY_BACKOFF_MS;
+    let mut failures = 0;
+    loop {
+        match attempt(target).await {
+            Err(_) if failures < retries => {
+                failures += 1;
+                sleep(Duration::from_millis(backoff)).await;
+                backoff *= 2;
+            }
+            res => return res,
+        }
+    }
+}
+
+// Gives up on `fut` (typically a grab_info) if it hasn't completed after `timeout`
+pub async fn read_with_timeout<T, F>(name: &str, timeout: Duration, fut: F) -> UnifiedResult<T>
+where
+    F: std::future::Future<Output = UnifiedResult<T>>,
+{
+    tokio::time::timeout(timeout, fut).await.map_err(|_| {
+        UnifiedError::Misc(format!("{name}: read timed out after {:.1}s", timeout.as_secs_f32()))
+    })?
+}
+
+/*
+   Fetches any read request from the channel without converting it into a TypedChannel first,
+   which would take it by value. The type is still checked by epics-ca when the data arrives.
+*/
+struct Fetch<R: ReadRequest + ?Sized>(PhantomData<fn(&R)>);
+
+impl<R: ReadRequest + ?Sized> Fetch<R> {
+    fn new() -> Self {
+        Fetch(PhantomData)
+    }
+}
+
+impl<R: ReadRequest + ?Sized> Callback for Fetch<R> {
+    type Request = R;
+    type Output = Box<R>;
+
+    fn apply(self, input: Result<&R, epics_ca::Error>) -> Result<Box<R>, epics_ca::Error> {
+        input.map(|req| req.clone_boxed())
+    }
+}
+
+/*
+   Expands to a match over every field type, invoking `$scalar` (if `$count` is 1) or `$array`
+   with the given arguments, followed by the Rust type of the value and the RawValue
+   constructor that wraps it. The result is whatever the invoked macro evaluates to.
+*/
+macro_rules! dispatch_field {
+    ($tp:expr, $count:expr, $scalar:ident, $array:ident, $($arg:expr),*) => {
+        if $count == 1 {
+            match $tp {
+                FieldId::Short => $scalar!($($arg),*, i16, RawValue::Short),
+                FieldId::Float => $scalar!($($arg),*, f32, RawValue::Float),
+                FieldId::Enum => $scalar!($($arg),*, EpicsEnum, RawValue::Enum),
+                FieldId::Char => $scalar!($($arg),*, u8, RawValue::Char),
+                FieldId::Long => $scalar!($($arg),*, i32, RawValue::Long),
+                FieldId::Double => $scalar!($($arg),*, f64, RawValue::Double),
+                FieldId::String => $scalar!($($arg),*, EpicsString, RawValue::String),
+            }
+        } else {
+            match $tp {
+                FieldId::Short => $array!($($arg),*, [i16], RawValue::ShortArray),
+                FieldId::Float => $array!($($arg),*, [f32], RawValue::FloatArray),
+                FieldId::Enum => $array!($($arg),*, [EpicsEnum], RawValue::EnumArray),
+                FieldId::Char => $array!($($arg),*, [u8], RawValue::CharArray),
+                FieldId::Long => $array!($($arg),*, [i32], RawValue::LongArray),
+                FieldId::Double => $array!($($arg),*, [f64], RawValue::DoubleArray),
+                FieldId::String => $array!($($arg),*, [EpicsString], RawValue::StringArray),
+            }
+        }
+    };
 }
 
 macro_rules! get_value {
     ($channel:expr, $V:ty, $F:expr) => {
-        $F($channel
-            .into_typed::<$V>()
-            .map_err(|(err, _)| UnifiedError::CaError(err))?
-            .get::<request::Time<$V>>()
-            .await
-            .map_err(|err| UnifiedError::CaError(err))?)
+        $F(*$channel
+            .get_with(Fetch::<request::Time<$V>>::new())
+            .await?)
     };
 }
 
 macro_rules! get_array {
     ($channel:expr, $V:ty, $F:expr) => {
         $F($channel
-            .into_typed::<$V>()
-            .map_err(|(err, _)| UnifiedError::CaError(err))?
-            .get_boxed::<request::Time<$V>>()
-            .await
-            .map_err(|err| UnifiedError::CaError(err))?)
+            .get_with(Fetch::<request::Time<$V>>::new())
+            .await?)
+    };
+}
+
+/*
+   epics-ca tags its CtrlEnum request as DBR_STS_ENUM, which means that the server never sends
+   back the enum strings. The layout is right, though, so we just wrap it with the proper id.
+*/
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+struct CtrlEnumRequest(request::CtrlEnum<EpicsEnum>);
+
+unsafe impl Request for CtrlEnumRequest {
+    type Raw = <EpicsEnum as Field>::CtrlRaw;
+    const ID: RequestId = RequestId::Ctrl(FieldId::Enum);
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    // Only the header is ever read, which is always there regardless of the element count
+    unsafe fn from_ptr<'a>(
+        ptr: *const u8,
+        dbr: RequestId,
+        _count: usize,
+    ) -> Result<&'a Self, epics_ca::Error> {
+        if dbr != Self::ID {
+            Err(error::BADTYPE)
+        } else {
+            Ok(&*(ptr as *const Self))
+        }
+    }
+
+    fn clone_boxed(&self) -> Box<Self> {
+        Box::new(*self)
+    }
+}
+
+impl ReadRequest for CtrlEnumRequest {}
+
+struct EnumStrings;
+
+impl Callback for EnumStrings {
+    type Request = CtrlEnumRequest;
+    type Output = Vec<String>;
+
+    fn apply(
+        self,
+        input: Result<&CtrlEnumRequest, epics_ca::Error>,
+    ) -> Result<Vec<String>, epics_ca::Error> {
+        input.map(|req| {
+            let ctrl = &req.0;
+            ctrl.strs
+                .iter()
+                .take(ctrl.no_str as usize)
+                .map(|s| s.to_string_lossy().to_string())
+                .collect()
+        })
+    }
+}
+
+pub async fn get_enum_strings(channel: &mut Channel) -> UnifiedResult<Vec<String>> {
+    Ok(channel.get_with(EnumStrings).await?)
+}
+
+/*
+   Reads the field the channel is named after: "pv" is the same as "pv.VAL", and "pv.DESC" reads
+   the description instead. The type is that of the field, so e.g. pv.DESC is a string even if
+   the value is a double. Menu fields (like pv.SCAN) come as enums, with their labels.
+*/
+pub async fn grab_info(channel: &mut Channel) -> UnifiedResult<Info> {
+    grab_info_as(channel, None).await
+}
+
+/*
+   Only enums can be read as DBF_ENUM and, while the server will try to convert a string to a
+   number, it fails for anything that doesn't look like one. Everything can be read as a string.
+*/
+fn check_conversion(native: FieldId, requested: FieldId) -> UnifiedResult<()> {
+    let incompatible = match requested {
+        FieldId::Enum => native != FieldId::Enum,
+        FieldId::String => false,
+        _ => native == FieldId::String,
+    };
+    if incompatible {
+        Err(UnifiedError::Misc(format!(
+            "Can't read a {} channel as {}",
+            field_type_name(native),
+            field_type_name(requested)
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/*
+   Native type and element count of the channel. Both are only known while it's connected, and
+   it may drop between `wait_connect` and the read.
+*/
+fn channel_shape(channel: &Channel) -> UnifiedResult<(FieldId, usize)> {
+    shape(channel.field_type(), channel.element_count())
+}
+
+fn shape(
+    field_type: Result<FieldId, error::Error>,
+    count: Result<usize, error::Error>,
+) -> UnifiedResult<(FieldId, usize)> {
+    match (field_type, count) {
+        (Ok(tp), Ok(count)) => Ok((tp, count)),
+        _ => Err(UnifiedError::Misc("channel disconnected before read".into())),
+    }
+}
+
+// Like grab_info, but `requested` (if given) overrides the native type of the channel
+pub async fn grab_info_as(channel: &mut Channel, requested: Option<FieldId>) -> UnifiedResult<Info> {
+    let (native, count) = channel_shape(channel)?;
+    let name = channel.name().to_string_lossy().to_string();
+    /*
+       Reading as DBR_STRING makes the server format the value, following the record (e.g. its
+       PREC), instead of sending the native value for us to format.
+    */
+    let tp = match requested {
+        Some(tp) => {
+            check_conversion(native, tp)?;
+            tp
+        }
+        None => native,
+    };
+    check_array_bytes(channel, tp)?;
+    let enum_strings = if tp == FieldId::Enum {
+        get_enum_strings(channel).await?
+    } else {
+        vec![]
+    };
+
+    let value = dispatch_field!(tp, count, get_value, get_array, channel);
+    let mut info = Info::new(name, count, value);
+    info.enum_strings = enum_strings;
+    info.field_type = field_type_name(native);
+
+    Ok(info)
+}
+
+pub fn connection_state(channel: &Channel) -> ConnectionState {
+    raw_connection_state(&ChannelId(channel.raw()))
+}
+
+/*
+   Resolves on the first SIGINT. The handler is installed right away, instead of on the first
+   poll, so a Ctrl-C that arrives before the tool starts waiting on it isn't lost.
+*/
+pub fn install_ctrl_c() -> impl Future<Output = ()> {
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if signal::ctrl_c().await.is_ok() {
+            let _ = tx.send(());
+        }
+    });
+    async move {
+        // If the handler couldn't be installed, never resolve
+        if rx.await.is_err() {
+            futures::future::pending::<()>().await;
+        }
+    }
+}
+
+/*
+   Channel ID that can be queried while the channel is borrowed elsewhere, e.g. by a running
+   subscription. Like `Channel` itself, it's safe to move across threads, and the state queries
+   can be made from any of them: the context is created with preemptive callbacks enabled.
+*/
+struct ChannelId(sys::chanId);
+
+unsafe impl Send for ChannelId {}
+unsafe impl Sync for ChannelId {}
+
+fn raw_connection_state(chid: &ChannelId) -> ConnectionState {
+    match unsafe { sys::ca_state(chid.0) } {
+        sys::channel_state::cs_never_conn => ConnectionState::NeverConnected,
+        sys::channel_state::cs_prev_conn => ConnectionState::PreviouslyConnected,
+        sys::channel_state::cs_conn => ConnectionState::Connected,
+        sys::channel_state::cs_closed => ConnectionState::Closed,
+    }
+}
+
+// Returns the (read, write) access rights for the channel
+pub fn access_rights(channel: &Channel) -> (bool, bool) {
+    unsafe {
+        (
+            sys::ca_read_access(channel.raw()) != 0,
+            sys::ca_write_access(channel.raw()) != 0,
+        )
+    }
+}
+
+// Fails, instead of panicking, if the channel is not connected
+pub fn describe(channel: &Channel) -> UnifiedResult<ChannelInfo> {
+    let (field_type, element_count) = channel_shape(channel)?;
+    let (read_access, write_access) = access_rights(channel);
+    Ok(ChannelInfo {
+        name: channel.name().to_string_lossy().to_string(),
+        field_type,
+        element_count,
+        state: connection_state(channel),
+        host: channel.host_name()?.to_string_lossy().to_string(),
+        read_access,
+        write_access,
+    })
+}
+
+macro_rules! get_ctrl {
+    ($channel:expr, $info:expr, $R:ident, $V:ty) => {{
+        let ctrl = $channel
+            .get_with(Fetch::<request::$R<[$V]>>::new())
+            .await?;
+        $info.units = Some(ctrl.units.0.to_string_lossy().to_string());
+        $info.limits = Some(Limits {
+            display: (ctrl.lower_disp_limit as f64, ctrl.upper_disp_limit as f64),
+            control: Some((ctrl.lower_ctrl_limit as f64, ctrl.upper_ctrl_limit as f64)),
+            warning: (ctrl.lower_warning_limit as f64, ctrl.upper_warning_limit as f64),
+            alarm: (ctrl.lower_alarm_limit as f64, ctrl.upper_alarm_limit as f64),
+        });
+        ctrl
+    }};
+}
+
+macro_rules! get_graphic {
+    ($channel:expr, $info:expr, $R:ident, $V:ty) => {{
+        let gr = $channel
+            .get_with(Fetch::<request::$R<[$V]>>::new())
+            .await?;
+        $info.units = Some(gr.units.0.to_string_lossy().to_string());
+        $info.limits = Some(Limits {
+            display: (gr.lower_disp_limit as f64, gr.upper_disp_limit as f64),
+            control: None,
+            warning: (gr.lower_warning_limit as f64, gr.upper_warning_limit as f64),
+            alarm: (gr.lower_alarm_limit as f64, gr.upper_alarm_limit as f64),
+        });
+        gr
+    }};
+}
+
+// Like grab_info, but also requests the Ctrl metadata (units, precision, limits)
+pub async fn grab_full_info(channel: &mut Channel) -> UnifiedResult<Info> {
+    grab_full_info_as(channel, None).await
+}
+
+/*
+   The Ctrl metadata is always requested using the native type. It describes the value, so it's
+   not requested for other fields (e.g. pv.HIHI), where it would be the record's and not theirs.
+*/
+pub async fn grab_full_info_as(
+    channel: &mut Channel,
+    requested: Option<FieldId>,
+) -> UnifiedResult<Info> {
+    let mut info = grab_info_as(channel, requested).await?;
+    if pv_field(&info.name) != "VAL" {
+        return Ok(info);
+    }
+
+    match channel_shape(channel)?.0 {
+        FieldId::Short => { get_ctrl!(channel, info, CtrlInt, i16); }
+        FieldId::Char => { get_ctrl!(channel, info, CtrlInt, u8); }
+        FieldId::Long => { get_ctrl!(channel, info, CtrlInt, i32); }
+        FieldId::Float => info.precision = Some(get_ctrl!(channel, info, CtrlFloat, f32).precision),
+        FieldId::Double => info.precision = Some(get_ctrl!(channel, info, CtrlFloat, f64).precision),
+        // Enums and strings have no units nor limits
+        FieldId::Enum | FieldId::String => (),
+    }
+
+    Ok(info)
+}
+
+/*
+   The record's display precision (PREC), for float and double values. The Ctrl request carries
+   the whole value again, so this is only worth calling when the precision is going to be used.
+   Other types, and fields other than VAL, have none.
+*/
+pub async fn grab_precision(channel: &mut Channel) -> UnifiedResult<Option<i16>> {
+    if pv_field(&channel.name().to_string_lossy()) != "VAL" {
+        return Ok(None);
+    }
+    Ok(match channel_shape(channel)?.0 {
+        FieldId::Float => Some(channel.get_with(Fetch::<request::CtrlFloat<[f32]>>::new()).await?.precision),
+        FieldId::Double => Some(channel.get_with(Fetch::<request::CtrlFloat<[f64]>>::new()).await?.precision),
+        _ => None,
+    })
+}
+
+/*
+   Like grab_full_info, but requests the Graphic metadata instead: the same, minus the control
+   limits. That's all a display needs to build its scales.
+*/
+pub async fn grab_graphic_info(channel: &mut Channel) -> UnifiedResult<Info> {
+    let mut info = grab_info(channel).await?;
+    if pv_field(&info.name) != "VAL" {
+        return Ok(info);
+    }
+
+    match channel_shape(channel)?.0 {
+        FieldId::Short => { get_graphic!(channel, info, GrInt, i16); }
+        FieldId::Char => { get_graphic!(channel, info, GrInt, u8); }
+        FieldId::Long => { get_graphic!(channel, info, GrInt, i32); }
+        FieldId::Float => info.precision = Some(get_graphic!(channel, info, GrFloat, f32).precision),
+        FieldId::Double => info.precision = Some(get_graphic!(channel, info, GrFloat, f64).precision),
+        FieldId::Enum | FieldId::String => (),
+    }
+
+    Ok(info)
+}
+
+/*
+   One-call read: creates the channel, waits up to `timeout` for it to connect, and fetches its
+   value as a Time<V> request of the native type, returning it as a populated Info.
+*/
+pub async fn get_pv(ctx: &Context, name: &str, timeout: Duration) -> UnifiedResult<Info> {
+    let mut infos = get_pvs(ctx, &[name.to_string()], timeout).await?;
+    Ok(infos.pop().unwrap())
+}
+
+// Same as get_pv, for several PVs. Fails if any of them does, and keeps the order of `names`
+pub async fn get_pvs(ctx: &Context, names: &[String], timeout: Duration) -> UnifiedResult<Vec<Info>> {
+    let (mut channels, mut failed) = get_channels(ctx, names)?;
+    if !failed.is_empty() {
+        return Err(failed.remove(0).1);
+    }
+    wait_connect(&mut channels, timeout.as_millis() as u64).await?;
+
+    let mut result = vec![];
+    for ch in channels.iter_mut() {
+        result.push(grab_info(ch).await?);
+    }
+    Ok(result)
+}
+
+struct Updates<R: ReadRequest + ?Sized>(VecDeque<Result<Box<R>, epics_ca::Error>>);
+
+impl<R: ReadRequest + ?Sized> Queue for Updates<R> {
+    type Request = R;
+    type Output = Box<R>;
+
+    fn push(&mut self, input: Result<&R, epics_ca::Error>) {
+        self.0.push_back(input.map(|req| req.clone_boxed()));
+    }
+
+    fn pop(&mut self) -> Option<Result<Box<R>, epics_ca::Error>> {
+        self.0.pop_front()
+    }
+}
+
+async fn subscribe_loop<R, W, E>(channel: &mut Channel, mask: EventMask, wrap: W, emit: &mut E)
+where
+    R: ReadRequest + ?Sized,
+    W: Fn(Box<R>) -> RawValue,
+    E: FnMut(UnifiedResult<RawValue>),
+{
+    let chid = ChannelId(channel.raw());
+    let mut subscription = channel.subscribe_with(Updates::<R>(VecDeque::new()));
+    subscription.set_event_mask(mask);
+    tokio::pin!(subscription);
+
+    // The subscription stream doesn't tell us about disconnections, so poll for them
+    let mut check = interval(Duration::from_millis(CONNECTION_POLL_MS));
+    loop {
+        select! {
+            update = subscription.next() => match update {
+                Some(update) => emit(update.map(&wrap).map_err(UnifiedError::from)),
+                None => break,
+            },
+            _ = check.tick() => {
+                if raw_connection_state(&chid) != ConnectionState::Connected {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+macro_rules! monitor_value {
+    ($channel:expr, $mask:expr, $emit:expr, $V:ty, $F:expr) => {
+        subscribe_loop::<request::Time<$V>, _, _>($channel, $mask, |req| $F(*req), $emit).await
     };
 }
 
-pub async fn grab_info(channel: Channel) -> UnifiedResult<Info> {
-    let count = channel.element_count().unwrap();
+macro_rules! monitor_array {
+    ($channel:expr, $mask:expr, $emit:expr, $V:ty, $F:expr) => {
+        subscribe_loop::<request::Time<$V>, _, _>($channel, $mask, $F, $emit).await
+    };
+}
+
+/*
+   Subscribes to the channel and calls `handle` for every update. Returns when the channel
+   disconnects, leaving it to the caller to wait for the reconnection and subscribe again.
+*/
+pub async fn monitor<F>(channel: &mut Channel, mask: EventMask, mut handle: F) -> UnifiedResult<()>
+where
+    F: FnMut(UnifiedResult<Info>),
+{
+    let (tp, count) = channel_shape(channel)?;
     let name = channel.name().to_string_lossy().to_string();
-    let tp = channel.field_type().unwrap();
-
-    Ok(Info::new(
-        name,
-        count,
-        if count == 1 {
-            match tp {
-                FieldId::Short => get_value!(channel, i16, RawValue::Short),
-                FieldId::Float => get_value!(channel, f32, RawValue::Float),
-                FieldId::Enum => get_value!(channel, EpicsEnum, RawValue::Enum),
-                FieldId::Char => get_value!(channel, u8, RawValue::Char),
-                FieldId::Long => get_value!(channel, i32, RawValue::Long),
-                FieldId::Double => get_value!(channel, f64, RawValue::Double),
-                FieldId::String => get_value!(channel, EpicsString, RawValue::String),
+    let enum_strings = if tp == FieldId::Enum {
+        get_enum_strings(channel).await?
+    } else {
+        vec![]
+    };
+
+    let mut emit = |update: UnifiedResult<RawValue>| {
+        handle(update.map(|value| {
+            let mut info = Info::new(name.clone(), count, value);
+            info.enum_strings = enum_strings.clone();
+            info
+        }))
+    };
+
+    dispatch_field!(tp, count, monitor_value, monitor_array, channel, mask, &mut emit);
+
+    Ok(())
+}
+
+/*
+   Stream version of `monitor`, yielding an Info per update. The subscription runs on its own
+   task, which waits for the channel to connect first, so this returns right away. The
+   subscription is renewed whenever the channel reconnects. If the monitor fails, its error is
+   the last item of the stream.
+*/
+pub async fn monitor_pv(
+    ctx: &Context,
+    name: &str,
+    mask: EventMask,
+) -> UnifiedResult<impl Stream<Item = UnifiedResult<Info>>> {
+    let mut channel = Channel::new(ctx, &CString::new(name)?)?;
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    /*
+       Once the stream is dropped there's nobody left to send to, so the task stops there. Dropping
+       the monitor future cancels the subscription, and the channel goes with the task.
+    */
+    tokio::spawn(async move {
+        loop {
+            let res = select! {
+                _ = tx.closed() => break,
+                res = async {
+                    channel.connected().await;
+                    monitor(&mut channel, mask, |update| {
+                        let _ = tx.send(update);
+                    })
+                    .await
+                } => res,
+            };
+            if let Err(e) = res {
+                let _ = tx.send(Err(e));
+                break;
             }
-        } else {
-            match tp {
-                FieldId::Short => get_array!(channel, [i16], RawValue::ShortArray),
-                FieldId::Float => get_array!(channel, [f32], RawValue::FloatArray),
-                FieldId::Long => get_array!(channel, [i32], RawValue::LongArray),
-                FieldId::Double => get_array!(channel, [f64], RawValue::DoubleArray),
-                FieldId::String => get_array!(channel, [EpicsString], RawValue::StringArray),
-                _ => unimplemented!(),
+        }
+    });
+
+    Ok(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|update| (update, rx))
+    }))
+}
+
+macro_rules! put_value {
+    ($channel:expr, $value:expr) => {
+        $channel
+            .put_ref(&$value)?
+            .await?
+    };
+}
+
+// Extracts the values of one variant from a list parsed for the same channel type
+macro_rules! parsed_array {
+    ($parsed:expr, $variant:ident) => {
+        $parsed
+            .iter()
+            .filter_map(|p| match p {
+                ParsedValue::$variant(v) => Some(*v),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+}
+
+pub async fn put_values(channel: &mut Channel, values: &[String]) -> UnifiedResult<()> {
+    let (tp, count) = channel_shape(channel)?;
+    let enum_strings = if tp == FieldId::Enum {
+        get_enum_strings(channel).await?
+    } else {
+        vec![]
+    };
+    let parsed = values
+        .iter()
+        .map(|v| parse_value(tp, v, &enum_strings))
+        .collect::<UnifiedResult<Vec<_>>>()?;
+
+    if count == 1 {
+        if parsed.len() != 1 {
+            return Err(UnifiedError::Misc("Only one value can be written to a scalar PV".into()));
+        }
+        match parsed[0] {
+            ParsedValue::Char(v) => put_value!(channel, v),
+            ParsedValue::Short(v) => put_value!(channel, v),
+            ParsedValue::Long(v) => put_value!(channel, v),
+            ParsedValue::Enum(v) => put_value!(channel, v),
+            ParsedValue::Float(v) => put_value!(channel, v),
+            ParsedValue::Double(v) => put_value!(channel, v),
+            ParsedValue::String(v) => put_value!(channel, v),
+        }
+    } else {
+        if parsed.len() > count {
+            return Err(UnifiedError::Misc(format!(
+                "{} values given, but the PV holds only {count}",
+                parsed.len()
+            )));
+        }
+        match tp {
+            FieldId::Char => put_value!(channel, parsed_array!(parsed, Char)[..]),
+            FieldId::Short => put_value!(channel, parsed_array!(parsed, Short)[..]),
+            FieldId::Long => put_value!(channel, parsed_array!(parsed, Long)[..]),
+            FieldId::Enum => put_value!(channel, parsed_array!(parsed, Enum)[..]),
+            FieldId::Float => put_value!(channel, parsed_array!(parsed, Float)[..]),
+            FieldId::Double => put_value!(channel, parsed_array!(parsed, Double)[..]),
+            FieldId::String => put_value!(channel, parsed_array!(parsed, String)[..]),
+        }
+    }
+
+    Ok(())
+}
+
+/*
+   One-call write: creates the channel, waits up to `timeout` for it to connect, parses `value`
+   according to the native type of the channel and writes it.
+*/
+pub async fn put_pv(ctx: &Context, name: &str, value: &str, timeout: Duration) -> UnifiedResult<()> {
+    put_pv_array(ctx, name, &[value.to_string()], timeout).await
+}
+
+// Same as put_pv, but writing several elements
+pub async fn put_pv_array(
+    ctx: &Context,
+    name: &str,
+    values: &[String],
+    timeout: Duration,
+) -> UnifiedResult<()> {
+    let (mut channels, mut failed) = get_channels(ctx, &[name.to_string()])?;
+    if let Some((_, error)) = failed.pop() {
+        return Err(error);
+    }
+    wait_connect(&mut channels, timeout.as_millis() as u64).await?;
+    put_values(&mut channels[0], values).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::pending;
+
+    #[test]
+    fn channel_names_are_checked_before_creating_channels() {
+        assert_eq!(channel_name("dev:pv").unwrap().to_bytes(), b"dev:pv");
+        assert!(channel_name("dev:\0pv").is_err());
+    }
+
+    #[test]
+    fn pv_lists_skip_blanks_and_comments() {
+        let text = "# magnets\n  dev:a  \n\ndev:b\n   # dev:c\n";
+        assert_eq!(parse_pv_list(text), ["dev:a", "dev:b"]);
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        set_verbosity(2);
+        assert!(log_enabled(1) && log_enabled(2));
+        assert!(!log_enabled(3));
+        set_verbosity(0);
+        assert!(!log_enabled(1));
+    }
+
+    #[test]
+    fn requested_types_must_make_sense() {
+        assert!(check_conversion(FieldId::Double, FieldId::String).is_ok());
+        assert!(check_conversion(FieldId::Long, FieldId::Double).is_ok());
+        assert!(check_conversion(FieldId::String, FieldId::Double).is_err());
+        assert!(check_conversion(FieldId::Long, FieldId::Enum).is_err());
+    }
+
+    #[test]
+    fn timeouts_name_the_missing_pvs() {
+        let e = connect_timeout(vec!["a".into(), "b".into()]).unwrap_err();
+        assert_eq!(e.to_string(), "Channel connect timed out: PV(s) not found: a, b");
+        assert!(connect_timeout(vec![]).is_ok());
+    }
+
+    #[test]
+    fn pv_specs_may_carry_a_timeout() {
+        assert_eq!(parse_pv_spec("dev:pv@2.5"), ("dev:pv".into(), Some(2.5)));
+        assert_eq!(parse_pv_spec("dev:pv"), ("dev:pv".into(), None));
+        // Not a positive number, so part of the name
+        assert_eq!(parse_pv_spec("dev:pv@0"), ("dev:pv@0".into(), None));
+        assert_eq!(parse_pv_spec("a@b@3"), ("a@b".into(), Some(3.0)));
+        assert_eq!(parse_pv_spec("@3"), ("@3".into(), None));
+    }
+
+    #[tokio::test]
+    async fn reads_time_out_on_their_own() {
+        let slow = read_with_timeout("pv", Duration::from_millis(100), pending::<UnifiedResult<()>>()).await;
+        assert!(matches!(slow, Err(UnifiedError::Misc(msg)) if msg == "pv: read timed out after 0.1s"));
+        let fast = read_with_timeout("pv", Duration::from_millis(10), async { Ok(1) }).await;
+        assert_eq!(fast.unwrap(), 1);
+    }
+
+    #[test]
+    fn a_dropped_channel_has_no_shape() {
+        assert!(matches!(shape(Ok(FieldId::Double), Ok(4)), Ok((FieldId::Double, 4))));
+        for (field_type, count) in [
+            (Err(error::DISCONN), Ok(4)),
+            (Ok(FieldId::Double), Err(error::DISCONN)),
+            (Err(error::DISCONN), Err(error::DISCONN)),
+        ] {
+            match shape(field_type, count) {
+                Err(UnifiedError::Misc(msg)) => assert_eq!(msg, "channel disconnected before read"),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn ctrl_c_waits_for_the_signal() {
+        let interrupted = tokio::time::timeout(Duration::from_millis(50), install_ctrl_c()).await;
+        assert!(interrupted.is_err());
+    }
+
+    #[test]
+    fn element_sizes() {
+        assert_eq!(element_bytes(FieldId::Char), 1);
+        assert_eq!(element_bytes(FieldId::Enum), 2);
+        assert_eq!(element_bytes(FieldId::Double), 8);
+        assert_eq!(element_bytes(FieldId::String), 40);
+    }
+
+    #[tokio::test]
+    async fn nothing_to_wait_for() {
+        // No channel left unconnected, so not even a zero timeout can expire
+        assert!(wait_connect(&mut [], 0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn progress_is_reported_while_connecting() {
+        let mut ticks = 0;
+        let timed_out = race_with_ticker(
+            pending::<()>(),
+            Duration::from_millis(350),
+            Some(Duration::from_millis(100)),
+            || ticks += 1,
+        )
+        .await;
+        assert!(timed_out);
+        // Every 100 ms for 350 ms, give or take the scheduling
+        assert!((2..=3).contains(&ticks), "{ticks} ticks");
+    }
+
+    #[tokio::test]
+    async fn no_progress_without_a_period() {
+        let mut ticks = 0;
+        let timed_out = race_with_ticker(async {}, Duration::from_millis(350), None, || ticks += 1).await;
+        assert!(!timed_out);
+        assert_eq!(ticks, 0);
+    }
+
+    #[tokio::test]
+    async fn retries_until_it_succeeds() {
+        let mut attempts = 0;
+        let res = with_retries(&mut attempts, 2, |attempts| {
+            async move {
+                *attempts += 1;
+                match *attempts {
+                    1 => Err(UnifiedError::ConnectTimeout(vec!["pv".into()])),
+                    _ => Ok(*attempts),
+                }
             }
-        },
-    ))
-}
\ No newline at end of file
+            .boxed()
+        })
+        .await;
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retries() {
+        let mut attempts = 0;
+        let res: UnifiedResult<()> = with_retries(&mut attempts, 1, |attempts| {
+            async move {
+                *attempts += 1;
+                Err(UnifiedError::NoPvs)
+            }
+            .boxed()
+        })
+        .await;
+        assert!(matches!(res, Err(UnifiedError::NoPvs)));
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn prefix_goes_before_every_name() {
+        let mut names = vec!["a".to_string(), "b.EGU".to_string()];
+        add_prefix(&mut names, "DEV:");
+        assert_eq!(names, ["DEV:a", "DEV:b.EGU"]);
+    }
+}
@@ -1,6 +1,27 @@
+pub mod ca_env;
+
+use epics_ca::types::{EventMask, FieldId};
+use regex::Regex;
+
+use crate::types::Op;
+
 pub const DEFAULT_WAIT_TIME: &str = "1.0";
+pub const DEFAULT_EVENT_MASK: &str = "va";
 pub const DEFAULT_TIMESTAMP: TimestampKind = TimestampKind::CAServer;
+pub const DEFAULT_NAME_WIDTH: &str = "30";
+pub const DEFAULT_MAX_CONCURRENT: &str = "256";
+pub const DEFAULT_RETRIES: &str = "0";
+pub const RETRY_BACKOFF_MS: u64 = 100;
+pub const CONNECTION_POLL_MS: u64 = 100;
+pub const PROGRESS_INTERVAL_MS: u64 = 1000;
+pub const DEFAULT_PRECISION: usize = 5;
+pub const DEFAULT_SEPARATOR: &str = " ";
+pub const DEFAULT_CSV_SEPARATOR: &str = ",";
+pub const DEFAULT_STAMP_DIGITS: usize = 6;
+pub const DEFAULT_PLACEHOLDER: &str = "N/A";
+pub const MAX_RECORD_NAME_LEN: usize = 60;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TimestampKind {
     CAServer,
     CAClient,
@@ -10,6 +31,78 @@ pub enum TimestampKind {
     Relative,
 }
 
+// Precision is the number of decimals for Fixed and Scientific, and significant digits for Shortest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatFormat {
+    Fixed(usize),
+    Scientific(usize),
+    Shortest(usize),
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat::Fixed(DEFAULT_PRECISION)
+    }
+}
+
+impl FloatFormat {
+    pub fn format(&self, value: f64) -> String {
+        match *self {
+            FloatFormat::Fixed(prec) => format!("{value:.prec$}"),
+            FloatFormat::Scientific(prec) => format!("{value:.prec$e}"),
+            FloatFormat::Shortest(prec) => format_shortest(value, prec.max(1)),
+        }
+    }
+
+    pub fn precision(&self) -> usize {
+        match *self {
+            FloatFormat::Fixed(prec) | FloatFormat::Scientific(prec) | FloatFormat::Shortest(prec) => prec,
+        }
+    }
+
+    // Same notation, different number of digits
+    pub fn with_precision(&self, prec: usize) -> Self {
+        match self {
+            FloatFormat::Fixed(_) => FloatFormat::Fixed(prec),
+            FloatFormat::Scientific(_) => FloatFormat::Scientific(prec),
+            FloatFormat::Shortest(_) => FloatFormat::Shortest(prec),
+        }
+    }
+}
+
+// Only zeros after the decimal point are dropped, so "100" stays as it is
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+// Drops the trailing zeros (and point) of a formatted float: 3.14000 is 3.14, 1.50000e3 is 1.5e3
+pub fn trim_zeros(s: &str) -> String {
+    match s.split_once('e') {
+        Some((mantissa, exp)) => format!("{}e{exp}", trim_fraction(mantissa)),
+        None => trim_fraction(s).to_string(),
+    }
+}
+
+// Mimics C's %g: scientific notation only for very small or large exponents, no trailing zeros
+fn format_shortest(value: f64, prec: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{value}");
+    }
+
+    let sci = format!("{:.*e}", prec - 1, value);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if exp < -4 || exp >= prec as i32 {
+        format!("{}e{exp}", trim_fraction(mantissa))
+    } else {
+        trim_fraction(&format!("{:.*}", (prec as i32 - 1 - exp) as usize, value)).to_string()
+    }
+}
+
 pub fn wait_time_in_range(s: &str) -> Result<f32, String> {
     let time: f32 = s
         .parse()
@@ -20,3 +113,311 @@ pub fn wait_time_in_range(s: &str) -> Result<f32, String> {
         Err("Wait time must be a positive value".into())
     }
 }
+
+pub fn tolerance(s: &str) -> Result<f64, String> {
+    let eps: f64 = s
+        .parse()
+        .map_err(|_| "The tolerance must be a real number".to_string())?;
+    if eps >= 0.0 {
+        Ok(eps)
+    } else {
+        Err("The tolerance can't be negative".into())
+    }
+}
+
+pub fn count_in_range(s: &str) -> Result<usize, String> {
+    let count: usize = s
+        .parse()
+        .map_err(|_| "The element count must be a positive integer".to_string())?;
+    if count >= 1 {
+        Ok(count)
+    } else {
+        Err("Element count must be at least 1".into())
+    }
+}
+
+/*
+   Array elements [start, end), Python style: either bound may be missing, and negative ones
+   count from the end of the array. They're resolved against the length of the actual value.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexRange {
+    pub start: Option<isize>,
+    pub end: Option<isize>,
+}
+
+impl IndexRange {
+    pub fn resolve(&self, len: usize) -> (usize, usize) {
+        let index = |i: isize| {
+            if i < 0 {
+                len.saturating_sub(i.unsigned_abs())
+            } else {
+                (i as usize).min(len)
+            }
+        };
+        let start = self.start.map_or(0, index);
+        let end = self.end.map_or(len, index);
+        (start, end.max(start))
+    }
+}
+
+// <start>:<end>. When both bounds have the same sign, start must not be past end
+pub fn index_range(s: &str) -> Result<IndexRange, String> {
+    let invalid = || format!("Invalid slice '{s}': use [start]:[end], with start <= end");
+    let bound = |b: &str| match b.trim() {
+        "" => Ok(None),
+        b => b.parse::<isize>().map(Some).map_err(|_| invalid()),
+    };
+    let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+    let range = IndexRange { start: bound(start)?, end: bound(end)? };
+    match (range.start, range.end) {
+        (Some(start), Some(end)) if (start < 0) == (end < 0) && start > end => Err(invalid()),
+        _ => Ok(range),
+    }
+}
+
+/*
+   Accepts DBR_<TYPE> and DBR_<KIND>_<TYPE>, case insensitive. The kind is ignored: the values are
+   always requested along with their time stamp.
+*/
+pub fn dbr_type(s: &str) -> Result<FieldId, String> {
+    let upper = s.to_uppercase();
+    let name = upper.strip_prefix("DBR_").unwrap_or(&upper);
+    let name = ["STS_", "TIME_", "GR_", "CTRL_"]
+        .iter()
+        .find_map(|kind| name.strip_prefix(kind))
+        .unwrap_or(name);
+
+    match name {
+        "STRING" => Ok(FieldId::String),
+        "CHAR" => Ok(FieldId::Char),
+        "SHORT" | "INT" => Ok(FieldId::Short),
+        "LONG" => Ok(FieldId::Long),
+        "ENUM" => Ok(FieldId::Enum),
+        "FLOAT" => Ok(FieldId::Float),
+        "DOUBLE" => Ok(FieldId::Double),
+        _ => Err(format!("Unknown DBR type '{s}'")),
+    }
+}
+
+/*
+   Record names are limited to MAX_RECORD_NAME_LEN characters. What follows the record name (a
+   field, as in pv.EGU, a filter, as in pv.{...}, or a subscript, as in pv[2]) is not counted, and
+   may contain anything but whitespace and control characters, like the rest of the name. The
+   errors don't repeat the name: the callers report it along with them.
+*/
+pub fn validate_pv_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Empty PV name".into());
+    }
+    if let Some(c) = name.chars().find(|c| c.is_whitespace() || c.is_control()) {
+        return Err(format!("Invalid character {c:?} in the PV name"));
+    }
+    let record = name.split(['.', '[', '{']).next().unwrap_or_default();
+    if record.chars().count() > MAX_RECORD_NAME_LEN {
+        return Err(format!("The record name is longer than {MAX_RECORD_NAME_LEN} characters"));
+    }
+    Ok(())
+}
+
+/*
+   The field a PV name refers to, as in pv.EGU. A bare record name, like a channel filter with no
+   field (pv.{...}), means VAL. The $ of long string access (pv.DESC$) is not part of the field.
+*/
+pub fn pv_field(name: &str) -> &str {
+    let name = name.split(['[', '{']).next().unwrap_or_default();
+    match name.split_once('.').map(|(_, field)| field.trim_end_matches('$')) {
+        Some(field) if !field.is_empty() => field,
+        _ => "VAL",
+    }
+}
+
+// Only checks the syntax: the names are filtered locally, not looked up on the servers
+pub fn name_pattern(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|error| format!("Invalid PV name pattern '{s}': {error}"))
+}
+
+pub fn comparison_op(s: &str) -> Result<Op, String> {
+    match s {
+        "==" => Ok(Op::Eq),
+        "!=" => Ok(Op::Ne),
+        ">" => Ok(Op::Gt),
+        ">=" => Ok(Op::Ge),
+        "<" => Ok(Op::Lt),
+        "<=" => Ok(Op::Le),
+        _ => Err(format!("Unknown operator '{s}', expected one of == != > >= < <=")),
+    }
+}
+
+pub fn event_mask(s: &str) -> Result<EventMask, String> {
+    let mut mask = EventMask::empty();
+    for c in s.chars() {
+        mask |= match c {
+            'v' => EventMask::VALUE,
+            'a' => EventMask::ALARM,
+            'l' => EventMask::ARCHIVE,
+            'p' => EventMask::PROPERTY,
+            _ => return Err(format!("Invalid event mask flag '{c}': use any of 'v', 'a', 'l', 'p'")),
+        };
+    }
+    if mask.is_empty() {
+        Err("The event mask can't be empty".into())
+    } else {
+        Ok(mask)
+    }
+}
+
+// Digits for the fractional seconds of a timestamp. EPICS stamps carry nanoseconds
+pub fn stamp_digits(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(digits @ (0 | 3 | 6 | 9)) => Ok(digits),
+        _ => Err(format!("Invalid timestamp precision '{s}': use one of 0, 3, 6, 9")),
+    }
+}
+
+pub fn timestamp_kind(s: &str) -> Result<TimestampKind, String> {
+    match s {
+        "s" => Ok(TimestampKind::CAServer),
+        "c" => Ok(TimestampKind::CAClient),
+        "i" => Ok(TimestampKind::Incremental),
+        "I" => Ok(TimestampKind::IncrementalByChannel),
+        "n" => Ok(TimestampKind::No),
+        "r" => Ok(TimestampKind::Relative),
+        _ => Err(format!("Invalid timestamp kind '{s}': use one of 's', 'c', 'i', 'I', 'n', 'r'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_masks() {
+        assert_eq!(event_mask("va"), Ok(EventMask::VALUE | EventMask::ALARM));
+        assert_eq!(event_mask("lp"), Ok(EventMask::ARCHIVE | EventMask::PROPERTY));
+        assert_eq!(event_mask(""), Err("The event mask can't be empty".into()));
+        assert!(event_mask("vx").unwrap_err().starts_with("Invalid event mask flag 'x'"));
+    }
+
+    #[test]
+    fn float_precision() {
+        assert_eq!(FloatFormat::Fixed(2).format(1.23456), "1.23");
+        assert_eq!(FloatFormat::Scientific(3).format(1234.5), "1.234e3");
+        assert_eq!(FloatFormat::Fixed(0).format(2.5), "2");
+    }
+
+    #[test]
+    fn shortest_is_like_printf_g() {
+        assert_eq!(format_shortest(1.23456, 3), "1.23");
+        assert_eq!(format_shortest(100.0, 6), "100");
+        assert_eq!(format_shortest(0.0001, 6), "0.0001");
+        assert_eq!(format_shortest(0.00001, 6), "1e-5");
+        assert_eq!(format_shortest(1234567.0, 3), "1.23e6");
+        assert_eq!(format_shortest(0.0, 3), "0");
+    }
+
+    #[test]
+    fn comparison_operators() {
+        assert_eq!(comparison_op(">="), Ok(Op::Ge));
+        assert_eq!(comparison_op("!="), Ok(Op::Ne));
+        assert!(comparison_op("=>").is_err());
+    }
+
+    #[test]
+    fn dbr_types() {
+        assert_eq!(dbr_type("DBR_DOUBLE"), Ok(FieldId::Double));
+        assert_eq!(dbr_type("dbr_time_long"), Ok(FieldId::Long));
+        assert_eq!(dbr_type("CTRL_ENUM"), Ok(FieldId::Enum));
+        assert_eq!(dbr_type("int"), Ok(FieldId::Short));
+        assert!(dbr_type("DBR_QUAD").is_err());
+    }
+
+    #[test]
+    fn timestamp_kinds() {
+        assert_eq!(timestamp_kind("i"), Ok(TimestampKind::Incremental));
+        assert_eq!(timestamp_kind("I"), Ok(TimestampKind::IncrementalByChannel));
+        assert_eq!(timestamp_kind("r"), Ok(TimestampKind::Relative));
+        assert!(timestamp_kind("x").is_err());
+    }
+
+    #[test]
+    fn stamp_digits_are_whole_units() {
+        assert_eq!(stamp_digits("9"), Ok(9));
+        assert_eq!(stamp_digits("0"), Ok(0));
+        assert!(stamp_digits("4").is_err());
+    }
+
+    #[test]
+    fn slices() {
+        assert_eq!(index_range("2:5"), Ok(IndexRange { start: Some(2), end: Some(5) }));
+        assert_eq!(index_range(" 0 : 3 "), Ok(IndexRange { start: Some(0), end: Some(3) }));
+        assert!(index_range("5:2").is_err());
+        assert!(index_range("3").is_err());
+        assert!(index_range("a:b").is_err());
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        assert_eq!(index_range("-3:").unwrap().resolve(10), (7, 10));
+        assert_eq!(index_range(":-1").unwrap().resolve(10), (0, 9));
+        assert_eq!(index_range("-5:-2").unwrap().resolve(10), (5, 8));
+        assert!(index_range("-2:-5").is_err());
+        // Past the start of the array, and a start after the end
+        assert_eq!(index_range("-20:").unwrap().resolve(10), (0, 10));
+        assert_eq!(index_range("8:-5").unwrap().resolve(10), (8, 8));
+    }
+
+    #[test]
+    fn tolerances_are_not_negative() {
+        assert_eq!(tolerance("0.01"), Ok(0.01));
+        assert_eq!(tolerance("0"), Ok(0.0));
+        assert!(tolerance("-1").is_err());
+        assert!(tolerance("tiny").is_err());
+    }
+
+    #[test]
+    fn trailing_zeros() {
+        assert_eq!(trim_zeros("3.14000"), "3.14");
+        assert_eq!(trim_zeros("1.50000e3"), "1.5e3");
+        assert_eq!(trim_zeros("2.000"), "2");
+        // Zeros before the point stay
+        assert_eq!(trim_zeros("100"), "100");
+        let opts = crate::types::FormatOpts { trim_zeros: true, ..Default::default() };
+        assert_eq!(opts.format_float(0.25), "0.25");
+    }
+
+    #[test]
+    fn name_patterns() {
+        assert!(name_pattern("^DEV:.*:TEMP$").unwrap().is_match("DEV:A:TEMP"));
+        assert!(name_pattern("(").unwrap_err().starts_with("Invalid PV name pattern '('"));
+    }
+
+    #[test]
+    fn valid_pv_names() {
+        for name in ["DEV:AI1", "rec.EGU", "rec.DESC$", r#"rec.{"dbnd":{"abs":1}}"#, "a[2]"] {
+            assert_eq!(validate_pv_name(name), Ok(()), "{name}");
+        }
+        // Only the record name counts towards the limit
+        let long = "x".repeat(MAX_RECORD_NAME_LEN);
+        assert!(validate_pv_name(&format!("{long}.DESC")).is_ok());
+    }
+
+    #[test]
+    fn invalid_pv_names() {
+        assert_eq!(validate_pv_name(""), Err("Empty PV name".into()));
+        assert_eq!(validate_pv_name("a b"), Err("Invalid character ' ' in the PV name".into()));
+        assert_eq!(validate_pv_name("a\u{7}"), Err("Invalid character '\\u{7}' in the PV name".into()));
+        let long = "x".repeat(MAX_RECORD_NAME_LEN + 1);
+        assert!(validate_pv_name(&long).unwrap_err().starts_with("The record name is longer than"));
+    }
+
+    #[test]
+    fn fields_of_pv_names() {
+        assert_eq!(pv_field("rec.EGU"), "EGU");
+        assert_eq!(pv_field("rec"), "VAL");
+        assert_eq!(pv_field("rec.VAL"), "VAL");
+        assert_eq!(pv_field("rec.DESC$"), "DESC");
+        assert_eq!(pv_field(r#"rec.{"ts":{}}"#), "VAL");
+        assert_eq!(pv_field("rec.INP[2]"), "INP");
+    }
+}
@@ -0,0 +1,133 @@
+/*
+   Channel Access reads its configuration from the environment when the context is created, so
+   any override has to be in place before calling Context::new()
+*/
+use std::env;
+
+pub const ADDR_LIST: &str = "EPICS_CA_ADDR_LIST";
+pub const AUTO_ADDR_LIST: &str = "EPICS_CA_AUTO_ADDR_LIST";
+pub const SERVER_PORT: &str = "EPICS_CA_SERVER_PORT";
+pub const MAX_ARRAY_BYTES: &str = "EPICS_CA_MAX_ARRAY_BYTES";
+
+// What libca assumes when EPICS_CA_MAX_ARRAY_BYTES is not set
+pub const DEFAULT_MAX_ARRAY_BYTES: usize = 16384;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaEnv {
+    pub addr_list: Vec<String>,
+    pub auto_addr_list: Option<bool>,
+    pub server_port: Option<u16>,
+    pub max_array_bytes: Option<usize>,
+}
+
+impl CaEnv {
+    pub fn from_env() -> Self {
+        CaEnv {
+            addr_list: env::var(ADDR_LIST).map(|s| parse_addr_list(&s)).unwrap_or_default(),
+            auto_addr_list: env::var(AUTO_ADDR_LIST).ok().and_then(|s| yes_no(&s).ok()),
+            server_port: env::var(SERVER_PORT).ok().and_then(|s| s.trim().parse().ok()),
+            max_array_bytes: env::var(MAX_ARRAY_BYTES).ok().and_then(|s| s.trim().parse().ok()),
+        }
+    }
+
+    pub fn max_array_bytes(&self) -> usize {
+        self.max_array_bytes.unwrap_or(DEFAULT_MAX_ARRAY_BYTES)
+    }
+
+    // Exports the settings to the environment. Unset ones keep whatever is in there already
+    pub fn apply(&self) {
+        if !self.addr_list.is_empty() {
+            set_addr_list(&self.addr_list);
+        }
+        if let Some(auto) = self.auto_addr_list {
+            set_auto_addr_list(auto);
+        }
+        if let Some(port) = self.server_port {
+            env::set_var(SERVER_PORT, port.to_string());
+        }
+        if let Some(bytes) = self.max_array_bytes {
+            env::set_var(MAX_ARRAY_BYTES, bytes.to_string());
+        }
+    }
+}
+
+// The address list is separated by whitespace, as libca expects it
+pub fn parse_addr_list(s: &str) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+}
+
+pub fn set_addr_list(addresses: &[String]) {
+    env::set_var(ADDR_LIST, addresses.join(" "));
+}
+
+pub fn set_auto_addr_list(auto: bool) {
+    env::set_var(AUTO_ADDR_LIST, if auto { "YES" } else { "NO" });
+}
+
+// Applies the --addr-list and --auto-addr command line overrides, if given
+pub fn apply_overrides(addr_list: Option<&String>, auto_addr_list: Option<bool>) {
+    if let Some(list) = addr_list {
+        set_addr_list(&parse_addr_list(list));
+    }
+    if let Some(auto) = auto_addr_list {
+        set_auto_addr_list(auto);
+    }
+}
+
+pub fn yes_no(s: &str) -> Result<bool, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "yes" | "y" | "true" => Ok(true),
+        "no" | "n" | "false" => Ok(false),
+        _ => Err(format!("Expected YES or NO, got '{s}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_lists_are_split_at_whitespace() {
+        assert_eq!(parse_addr_list(" 10.0.0.1  10.0.0.2:5064\t\n"), ["10.0.0.1", "10.0.0.2:5064"]);
+        assert!(parse_addr_list("   ").is_empty());
+    }
+
+    #[test]
+    fn yes_or_no() {
+        assert_eq!(yes_no("YES"), Ok(true));
+        assert_eq!(yes_no(" n "), Ok(false));
+        assert!(yes_no("maybe").is_err());
+    }
+
+    #[test]
+    fn settings_go_through_the_environment() {
+        // Put back whatever the environment had, so that the contexts in other tests aren't affected
+        let vars = [ADDR_LIST, AUTO_ADDR_LIST, SERVER_PORT, MAX_ARRAY_BYTES];
+        let saved: Vec<_> = vars.iter().map(env::var_os).collect();
+
+        let cfg = CaEnv {
+            addr_list: vec!["10.0.0.1".into(), "10.0.0.2".into()],
+            auto_addr_list: Some(false),
+            server_port: Some(5070),
+            max_array_bytes: Some(100000),
+        };
+        cfg.apply();
+        assert_eq!(env::var(ADDR_LIST).unwrap(), "10.0.0.1 10.0.0.2");
+        assert_eq!(env::var(AUTO_ADDR_LIST).unwrap(), "NO");
+        assert_eq!(CaEnv::from_env(), cfg);
+
+        for (var, value) in vars.iter().zip(saved) {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+    }
+
+    #[test]
+    fn max_array_bytes_defaults_to_libca_one() {
+        assert_eq!(CaEnv::default().max_array_bytes(), DEFAULT_MAX_ARRAY_BYTES);
+        let cfg = CaEnv { max_array_bytes: Some(1 << 20), ..Default::default() };
+        assert_eq!(cfg.max_array_bytes(), 1 << 20);
+    }
+}
@@ -1,13 +1,128 @@
+use std::{ffi::{CStr, NulError}, fmt};
+
 pub mod common;
 pub mod config;
 pub mod types;
 
+#[cfg(any(test, feature = "test-util"))]
+#[doc(hidden)]
+pub mod test_support;
+
 pub use common::*;
 
 pub type UnifiedResult<T> = Result<T, UnifiedError>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UnifiedError {
     CaError(epics_ca::Error),
+    // Names of the PVs that didn't connect in time
+    ConnectTimeout(Vec<String>),
+    // None of the requested PVs could be read
+    NoPvs,
+    // The condition waited for (e.g. by rcawait) wasn't met in time
+    ConditionTimeout,
+    // The user hit Ctrl-C
+    Interrupted,
     Misc(String),
 }
+
+impl UnifiedError {
+    /*
+       Exit status for the binaries: 1 for generic errors, 2 for timeouts, 3 if no PV was found,
+       and 130 (128 + SIGINT, as the shells do) when interrupted
+    */
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            UnifiedError::ConnectTimeout(_) | UnifiedError::ConditionTimeout => 2,
+            UnifiedError::NoPvs => 3,
+            UnifiedError::Interrupted => 130,
+            UnifiedError::CaError(_) | UnifiedError::Misc(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for UnifiedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnifiedError::CaError(err) => {
+                // ca_message returns a pointer to a static string, or null for unknown codes
+                let msg = unsafe { epics_ca_sys::ca_message(err.into_raw() as _) };
+                if msg.is_null() {
+                    write!(f, "{:?} ({:?})", err.kind, err.severity)
+                } else {
+                    write!(f, "{}", unsafe { CStr::from_ptr(msg) }.to_string_lossy())
+                }
+            }
+            UnifiedError::ConnectTimeout(names) => {
+                write!(f, "Channel connect timed out: PV(s) not found: {}", names.join(", "))
+            }
+            UnifiedError::NoPvs => f.write_str("No PVs found"),
+            UnifiedError::ConditionTimeout => f.write_str("Timed out waiting for the condition"),
+            UnifiedError::Interrupted => f.write_str("Interrupted"),
+            UnifiedError::Misc(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<epics_ca::Error> for UnifiedError {
+    fn from(err: epics_ca::Error) -> Self {
+        UnifiedError::CaError(err)
+    }
+}
+
+impl From<NulError> for UnifiedError {
+    fn from(err: NulError) -> Self {
+        UnifiedError::Misc(format!("{err}"))
+    }
+}
+
+// epics_ca::Error doesn't implement std::error::Error, so there's no source to expose
+impl std::error::Error for UnifiedError {}
+
+/*
+   Boxed errors (as used by anyhow or eyre) must be thread safe, so `?` can only take a
+   UnifiedError into them if it's Send + Sync. This fails to build if that stops being the case.
+*/
+const _: fn() = || {
+    fn boxable<E: std::error::Error + Send + Sync + 'static>() {}
+    boxable::<UnifiedError>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_display_their_message() {
+        assert_eq!(UnifiedError::Misc("oops".into()).to_string(), "oops");
+        let boxed: Box<dyn std::error::Error> = Box::new(UnifiedError::Misc("oops".into()));
+        assert_eq!(boxed.to_string(), "oops");
+    }
+
+    #[test]
+    fn ca_errors_convert_into_unified_errors() {
+        let err: UnifiedError = epics_ca::error::TIMEOUT.into();
+        assert!(matches!(err, UnifiedError::CaError(e) if e.kind == epics_ca::error::ErrorKind::Timeout));
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(UnifiedError::Misc("oops".into()).exit_code(), 1);
+        assert_eq!(UnifiedError::ConnectTimeout(vec![]).exit_code(), 2);
+        assert_eq!(UnifiedError::ConditionTimeout.exit_code(), 2);
+        assert_eq!(UnifiedError::NoPvs.exit_code(), 3);
+        assert_eq!(UnifiedError::Interrupted.exit_code(), 130);
+    }
+
+    #[test]
+    fn question_mark_boxes_the_errors() {
+        fn fails() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            Err(UnifiedError::NoPvs)?
+        }
+        let e = fails().unwrap_err();
+        assert!(matches!(e.downcast_ref::<UnifiedError>(), Some(UnifiedError::NoPvs)));
+        // And the box can go to another thread
+        let message = std::thread::spawn(move || e.to_string()).join().unwrap();
+        assert_eq!(message, "No PVs found");
+    }
+}
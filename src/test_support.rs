@@ -0,0 +1,36 @@
+/*
+   Values that didn't come from a server, to test the formatting. They carry no alarm, and the
+   time stamp is the EPICS epoch: both are public fields, to be set as needed.
+
+   Only built for the tests (the binaries' get it through the test-util feature). This is not
+   part of the supported API.
+*/
+use std::mem;
+
+use epics_ca::{
+    request::{self, Request},
+    types::Field,
+};
+
+pub fn time_array<T: Field>(values: &[T]) -> Box<request::Time<[T]>> {
+    // The value is the last field of the raw structure, and the array extends it
+    let header = mem::size_of::<T::TimeRaw>() - mem::size_of::<T>();
+    let bytes = header + mem::size_of_val(values).max(mem::size_of::<T>());
+    let mut buffer = vec![0u64; bytes.div_ceil(mem::size_of::<u64>())];
+    unsafe {
+        let base = buffer.as_mut_ptr() as *mut u8;
+        std::ptr::copy_nonoverlapping(values.as_ptr() as *const u8, base.add(header), mem::size_of_val(values));
+        request::Time::<[T]>::from_ptr(base, request::Time::<[T]>::ID, values.len())
+            .expect("the buffer is laid out as libca would")
+            .clone_boxed()
+    }
+}
+
+pub fn time_value<T: Field>(value: T) -> request::Time<T> {
+    let array = time_array(&[value]);
+    let ptr = &*array as *const request::Time<[T]> as *const u8;
+    unsafe {
+        *request::Time::<T>::from_ptr(ptr, request::Time::<T>::ID, 1)
+            .expect("a single element array is laid out as a scalar")
+    }
+}
@@ -1,5 +1,24 @@
-use chrono::{Local, DateTime};
-use epics_ca::{request, types::{EpicsEnum, EpicsString, EpicsTimeStamp}};
+use std::{borrow::Cow, collections::HashMap, ffi::CString, fmt, str::FromStr, time::SystemTime};
+
+use chrono::{DateTime, Local, Utc};
+use crate::{
+    config::{trim_zeros, FloatFormat, TimestampKind, DEFAULT_SEPARATOR, DEFAULT_STAMP_DIGITS},
+    UnifiedError,
+    UnifiedResult,
+};
+use epics_ca::{
+    request,
+    types::{EpicsEnum, EpicsString, EpicsTimeStamp, FieldId},
+};
+
+/*
+   epics-ca already maps the raw alarm codes (try_from_raw) to these, and Info holds them typed.
+   Re-exported so that library users don't need to depend on epics-ca for them.
+*/
+pub use epics_ca::types::{AlarmCondition, AlarmSeverity};
+
+// Including the NUL terminator
+const MAX_STRING_SIZE: usize = epics_ca_sys::MAX_STRING_SIZE as usize;
 
 
 #[derive(Debug)]
@@ -13,25 +32,33 @@ pub enum RawValue {
     Double(request::Time<f64>),
     String(request::Time<EpicsString>),
     // Arrays
+    CharArray(Box<request::Time<[u8]>>),
     ShortArray(Box<request::Time<[i16]>>),
     LongArray(Box<request::Time<[i32]>>),
+    EnumArray(Box<request::Time<[EpicsEnum]>>),
     FloatArray(Box<request::Time<[f32]>>),
     DoubleArray(Box<request::Time<[f64]>>),
     StringArray(Box<request::Time<[EpicsString]>>),
 }
 
-macro_rules! impl_get_stamp {
-    ($op:ident, $( $name:ident ),+) => {
+macro_rules! impl_get_field {
+    ($op:ident, $field:ident, $( $name:ident ),+) => {
         match $op {
-            $(RawValue::$name(val) => val.stamp,)+
+            $(RawValue::$name(val) => val.$field,)+
         }
     };
 }
 
 impl RawValue {
+    // EPICS strings end at the first NUL. Whatever the buffer holds after it is ignored
+    pub fn string_lossy_trimmed(s: &EpicsString) -> String {
+        String::from_utf8_lossy(s.to_bytes()).into_owned()
+    }
+
     pub fn get_stamp(&self) -> EpicsTimeStamp {
-        impl_get_stamp!(
+        impl_get_field!(
             self,
+            stamp,
             Char,
             Short,
             Long,
@@ -39,59 +66,731 @@ impl RawValue {
             Double,
             Enum,
             String,
+            CharArray,
             ShortArray,
             LongArray,
+            EnumArray,
             FloatArray,
             DoubleArray,
             StringArray
         )
     }
 
-    pub fn format_scalar(&self) -> String {
+    pub fn get_alarm(&self) -> (AlarmCondition, AlarmSeverity) {
+        let alarm = impl_get_field!(
+            self,
+            alarm,
+            Char,
+            Short,
+            Long,
+            Float,
+            Double,
+            Enum,
+            String,
+            CharArray,
+            ShortArray,
+            LongArray,
+            EnumArray,
+            FloatArray,
+            DoubleArray,
+            StringArray
+        );
+        (alarm.condition, alarm.severity)
+    }
+
+    // DBF type of the value, regardless of it being a scalar or an array
+    pub fn field_id(&self) -> FieldId {
         match self {
-            RawValue::Short(val) => format!("{}", val.value),
-            RawValue::Long(val) => format!("{}", val.value),
-            RawValue::Float(val) => format!("{:.5}", val.value),
-            RawValue::Double(val) => format!("{:.5}", val.value),
+            RawValue::Char(_) | RawValue::CharArray(_) => FieldId::Char,
+            RawValue::Short(_) | RawValue::ShortArray(_) => FieldId::Short,
+            RawValue::Long(_) | RawValue::LongArray(_) => FieldId::Long,
+            RawValue::Enum(_) | RawValue::EnumArray(_) => FieldId::Enum,
+            RawValue::Float(_) | RawValue::FloatArray(_) => FieldId::Float,
+            RawValue::Double(_) | RawValue::DoubleArray(_) => FieldId::Double,
+            RawValue::String(_) | RawValue::StringArray(_) => FieldId::String,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        field_type_name(self.field_id())
+    }
+
+    // Numeric scalars only. Enums are indices, not quantities, so they don't count
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            RawValue::Char(val) => Some(val.value as f64),
+            RawValue::Short(val) => Some(val.value as f64),
+            RawValue::Long(val) => Some(val.value as f64),
+            RawValue::Float(val) => Some(val.value as f64),
+            RawValue::Double(val) => Some(val.value),
+            _ => None,
+        }
+    }
+
+    // Numeric arrays only, like `as_f64`. None for an empty array, too
+    pub fn array_stats(&self) -> Option<ArrayStats> {
+        match self {
+            RawValue::CharArray(val) => ArrayStats::from_values(val.value.iter().map(|&v| v as f64)),
+            RawValue::ShortArray(val) => ArrayStats::from_values(val.value.iter().map(|&v| v as f64)),
+            RawValue::LongArray(val) => ArrayStats::from_values(val.value.iter().map(|&v| v as f64)),
+            RawValue::FloatArray(val) => ArrayStats::from_values(val.value.iter().map(|&v| v as f64)),
+            RawValue::DoubleArray(val) => ArrayStats::from_values(val.value.iter().copied()),
+            _ => None,
+        }
+    }
+
+    // The elements that came with the value, as they would be written
+    pub fn elements(&self) -> Vec<ParsedValue> {
+        match self {
+            RawValue::Char(val) => vec![ParsedValue::Char(val.value)],
+            RawValue::Short(val) => vec![ParsedValue::Short(val.value)],
+            RawValue::Long(val) => vec![ParsedValue::Long(val.value)],
+            RawValue::Enum(val) => vec![ParsedValue::Enum(val.value)],
+            RawValue::Float(val) => vec![ParsedValue::Float(val.value)],
+            RawValue::Double(val) => vec![ParsedValue::Double(val.value)],
+            RawValue::String(val) => vec![ParsedValue::String(val.value)],
+            RawValue::CharArray(val) => val.value.iter().map(|&v| ParsedValue::Char(v)).collect(),
+            RawValue::ShortArray(val) => val.value.iter().map(|&v| ParsedValue::Short(v)).collect(),
+            RawValue::LongArray(val) => val.value.iter().map(|&v| ParsedValue::Long(v)).collect(),
+            RawValue::EnumArray(val) => val.value.iter().map(|&v| ParsedValue::Enum(v)).collect(),
+            RawValue::FloatArray(val) => val.value.iter().map(|&v| ParsedValue::Float(v)).collect(),
+            RawValue::DoubleArray(val) => val.value.iter().map(|&v| ParsedValue::Double(v)).collect(),
+            RawValue::StringArray(val) => val.value.iter().map(|&v| ParsedValue::String(v)).collect(),
+        }
+    }
+
+    // Elements [start, end) of a char array, in `hexdump` format. None for any other value
+    pub fn hexdump(&self, start: usize, end: usize) -> Option<Vec<String>> {
+        match self {
+            RawValue::CharArray(val) => {
+                let end = end.min(val.value.len());
+                Some(hexdump(&val.value[start.min(end)..end], start))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RawValue::CharArray(val) => val.value.len(),
+            RawValue::ShortArray(val) => val.value.len(),
+            RawValue::LongArray(val) => val.value.len(),
+            RawValue::EnumArray(val) => val.value.len(),
+            RawValue::FloatArray(val) => val.value.len(),
+            RawValue::DoubleArray(val) => val.value.len(),
+            RawValue::StringArray(val) => val.value.len(),
+            _ => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // All the formatting methods take their knobs from `opts`
+    pub fn format_scalar(&self, opts: &FormatOpts) -> String {
+        match self {
+            RawValue::Char(val) if opts.char_as_string => char_literal(val.value),
+            RawValue::Char(val) => opts.format_int(val.value),
+            RawValue::Short(val) => opts.format_int(val.value),
+            RawValue::Long(val) => opts.format_int(val.value),
+            RawValue::Float(val) => opts.format_float(val.value as f64),
+            RawValue::Double(val) => opts.format_float(val.value),
             RawValue::Enum(val) => format!("{}", val.value.0),
-            RawValue::String(val) => val.value.to_string_lossy().to_string(),
+            RawValue::String(val) => RawValue::string_lossy_trimmed(&val.value),
             _ => format!("<formatting not implemented yet for {self:#?}>"),
         }
     }
 
-    pub fn format_array(&self, padding: usize) -> String {
-        fn format_array_inner<T>(padding: usize, data: &request::Time<[T]>) -> String
+    pub fn format_array(&self, padding: usize, opts: &FormatOpts) -> String {
+        join_elements(self.format_elements(padding, opts), opts)
+    }
+
+    // Same as format_array, but leaves every element in its own string
+    pub fn format_elements(&self, padding: usize, opts: &FormatOpts) -> Vec<String> {
+        self.format_elements_labeled(padding, opts, &[])
+    }
+
+    // Enum array elements are printed using `labels`, if they're in range
+    pub fn format_elements_labeled(
+        &self,
+        padding: usize,
+        opts: &FormatOpts,
+        labels: &[String],
+    ) -> Vec<String> {
+        fn format_array_inner<T, F>(
+            padding: usize,
+            data: &request::Time<[T]>,
+            filler: &str,
+            fmt: F,
+        ) -> Vec<String>
         where
-            T: ToString,
+            F: Fn(&T) -> String,
             [T]: epics_ca::types::Value,
         {
-            let mut rest: Vec<_> = data.value.iter().map(|d| d.to_string()).collect();
-            for _ in 0..(padding - rest.len()) {
-                rest.push("0".into());
+            let mut rest: Vec<_> = data.value.iter().take(padding).map(fmt).collect();
+            for _ in 0..padding.saturating_sub(rest.len()) {
+                rest.push(filler.into());
             }
-            rest.join(" ").to_string()
+            rest
         }
 
         match self {
-            RawValue::LongArray(val) => format_array_inner(padding, val),
-            _ => format!("<formatting not implemented yet for {self:#?}>"),
+            RawValue::CharArray(val) if opts.char_as_string => {
+                let len = padding.min(val.value.len());
+                vec![char_string(&val.value[..len])]
+            }
+            RawValue::CharArray(val) => format_array_inner(padding, val, "0", |v| opts.format_int(*v)),
+            RawValue::ShortArray(val) => format_array_inner(padding, val, "0", |v| opts.format_int(*v)),
+            RawValue::LongArray(val) => format_array_inner(padding, val, "0", |v| opts.format_int(*v)),
+            RawValue::EnumArray(val) => format_array_inner(padding, val, "0", |v| {
+                labels.get(v.0 as usize).cloned().unwrap_or_else(|| v.0.to_string())
+            }),
+            RawValue::FloatArray(val) => format_array_inner(padding, val, "0", |v| opts.format_float(*v as f64)),
+            RawValue::DoubleArray(val) => format_array_inner(padding, val, "0", |v| opts.format_float(*v)),
+            RawValue::StringArray(val) => {
+                format_array_inner(padding, val, "", RawValue::string_lossy_trimmed)
+            }
+            _ => vec![format!("<formatting not implemented yet for {self:#?}>")],
+        }
+    }
+}
+
+// A single element, parsed into the native type of a channel, ready to be written
+#[derive(Debug, Clone, Copy)]
+pub enum ParsedValue {
+    Char(u8),
+    Short(i16),
+    Long(i32),
+    Enum(EpicsEnum),
+    Float(f32),
+    Double(f64),
+    String(EpicsString),
+}
+
+impl ParsedValue {
+    // Floats may differ by up to `tolerance`, to allow for rounding in the record
+    pub fn matches(&self, other: &ParsedValue, tolerance: f64) -> bool {
+        match (self, other) {
+            (ParsedValue::Char(a), ParsedValue::Char(b)) => a == b,
+            (ParsedValue::Short(a), ParsedValue::Short(b)) => a == b,
+            (ParsedValue::Long(a), ParsedValue::Long(b)) => a == b,
+            (ParsedValue::Enum(a), ParsedValue::Enum(b)) => a.0 == b.0,
+            (ParsedValue::Float(a), ParsedValue::Float(b)) => (*a as f64 - *b as f64).abs() <= tolerance,
+            (ParsedValue::Double(a), ParsedValue::Double(b)) => (a - b).abs() <= tolerance,
+            (ParsedValue::String(a), ParsedValue::String(b)) => {
+                RawValue::string_lossy_trimmed(a) == RawValue::string_lossy_trimmed(b)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Integers may be given in hex (0x) or octal (0o), with an optional sign
+fn parse_integer(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(oct) = lower.strip_prefix("0o") {
+        i64::from_str_radix(oct, 8).ok()?
+    } else {
+        lower.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn conversion_error(tp: FieldId, s: &str) -> UnifiedError {
+    UnifiedError::Misc(format!("Can't convert '{s}' to {}", field_type_name(tp)))
+}
+
+fn range_error(tp: FieldId, s: impl fmt::Display) -> UnifiedError {
+    UnifiedError::Misc(format!("Value {s} out of range for {}", field_type_name(tp)))
+}
+
+fn parse_in_range<T: TryFrom<i64>>(tp: FieldId, s: &str) -> UnifiedResult<T> {
+    let value = parse_integer(s.trim()).ok_or_else(|| conversion_error(tp, s))?;
+    T::try_from(value).map_err(|_| range_error(tp, value))
+}
+
+fn parse_float<T: FromStr + Into<f64> + Copy>(tp: FieldId, s: &str) -> UnifiedResult<T> {
+    let value: T = s.trim().parse().map_err(|_| conversion_error(tp, s))?;
+    // An input that was finite, but overflowed the type
+    if value.into().is_infinite() && s.trim().parse::<f64>().is_ok_and(f64::is_finite) {
+        return Err(range_error(tp, s));
+    }
+    Ok(value)
+}
+
+/*
+   Parses `s` as a value of type `tp`, checking that it fits. Enums accept either one of their
+   labels (from `enum_strings`) or an index. Strings must fit in an EPICS string (39 characters,
+   plus the terminator); see `truncate_epics_string`.
+*/
+pub fn parse_value(tp: FieldId, s: &str, enum_strings: &[String]) -> UnifiedResult<ParsedValue> {
+    Ok(match tp {
+        FieldId::Char => ParsedValue::Char(parse_in_range(tp, s)?),
+        FieldId::Short => ParsedValue::Short(parse_in_range(tp, s)?),
+        FieldId::Long => ParsedValue::Long(parse_in_range(tp, s)?),
+        FieldId::Float => ParsedValue::Float(parse_float(tp, s)?),
+        FieldId::Double => ParsedValue::Double(parse_float(tp, s)?),
+        FieldId::Enum => match enum_strings.iter().position(|label| label == s) {
+            Some(idx) => ParsedValue::Enum(EpicsEnum(idx as u16)),
+            // Not a label, nor a number: say which labels there are
+            None if !enum_strings.is_empty() && parse_integer(s.trim()).is_none() => {
+                return Err(UnifiedError::Misc(format!(
+                    "'{s}' is not a valid state, expected an index or one of: {}",
+                    enum_strings.join(", ")
+                )));
+            }
+            None => {
+                let idx: u16 = parse_in_range(tp, s)?;
+                if !enum_strings.is_empty() && idx as usize >= enum_strings.len() {
+                    return Err(UnifiedError::Misc(format!(
+                        "'{s}' is not a valid state: there are only {}",
+                        enum_strings.len()
+                    )));
+                }
+                ParsedValue::Enum(EpicsEnum(idx))
+            }
+        },
+        FieldId::String => {
+            let cstr = CString::new(s)?;
+            ParsedValue::String(EpicsString::from_cstr(&cstr).ok_or_else(|| {
+                UnifiedError::Misc(format!("String too long for an EPICS string: '{s}'"))
+            })?)
+        }
+    })
+}
+
+// Cuts `s` so that it fits in an EPICS string, without splitting a character
+pub fn truncate_epics_string(s: &str) -> &str {
+    let max = MAX_STRING_SIZE - 1;
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn align_elements(elements: &mut [String], opts: &FormatOpts) {
+    if opts.align_columns {
+        // Elements are already formatted, so this covers signs and the float precision
+        let width = elements.iter().map(|e| e.chars().count()).max().unwrap_or(0);
+        for e in elements.iter_mut() {
+            *e = format!("{e:>width$}");
+        }
+    }
+}
+
+fn join_elements(mut elements: Vec<String>, opts: &FormatOpts) -> String {
+    align_elements(&mut elements, opts);
+    elements.join(&opts.separator)
+}
+
+// Like join_elements, but `per_line` elements at a time. Columns are aligned across all lines
+pub fn wrap_elements(mut elements: Vec<String>, per_line: usize, opts: &FormatOpts) -> Vec<String> {
+    align_elements(&mut elements, opts);
+    elements
+        .chunks(per_line.max(1))
+        .map(|line| line.join(&opts.separator))
+        .collect()
+}
+
+const SI_PREFIXES: [(i32, &str); 11] = [
+    (-12, "p"),
+    (-9, "n"),
+    (-6, "µ"),
+    (-3, "m"),
+    (0, ""),
+    (3, "k"),
+    (6, "M"),
+    (9, "G"),
+    (12, "T"),
+    (15, "P"),
+    (18, "E"),
+];
+
+// Engineering notation with an SI prefix, and `prec` decimals: 1500000 is "1.50M" with prec = 2
+pub fn format_human(value: f64, prec: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{value:.prec$}");
+    }
+
+    let exp = (value.abs().log10() / 3.0).floor() as i32 * 3;
+    let mut idx = SI_PREFIXES
+        .iter()
+        .position(|&(e, _)| e == exp.clamp(-12, 18))
+        .unwrap();
+    let mut scaled = format!("{:.prec$}", value / 10f64.powi(SI_PREFIXES[idx].0));
+    // Rounding may take us to the next prefix, as in 999999.9 -> "1000.00k"
+    if scaled.trim_start_matches('-').starts_with("1000") && idx + 1 < SI_PREFIXES.len() {
+        idx += 1;
+        scaled = format!("{:.prec$}", value / 10f64.powi(SI_PREFIXES[idx].0));
+    }
+    format!("{scaled}{}", SI_PREFIXES[idx].1)
+}
+
+// Bytes up to the first NUL, like a C string. Invalid UTF-8 gets replaced
+pub fn char_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+// Printable ASCII as is, anything else as a \xNN escape
+pub fn char_literal(byte: u8) -> String {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        char::from(byte).to_string()
+    } else {
+        format!("\\x{byte:02x}")
+    }
+}
+
+pub const HEXDUMP_WIDTH: usize = 16;
+
+/*
+   Like `hexdump -C`: offset, HEXDUMP_WIDTH bytes in hex and the same bytes as ASCII, with dots
+   for anything not printable. `offset` is the position of the first byte in the array.
+*/
+pub fn hexdump(data: &[u8], offset: usize) -> Vec<String> {
+    data.chunks(HEXDUMP_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<_> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { char::from(b) } else { '.' })
+                .collect();
+            format!(
+                "{:08x}  {:<width$}  |{ascii}|",
+                offset + i * HEXDUMP_WIDTH,
+                hex.join(" "),
+                width = HEXDUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect()
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// JSON has no representation for NaN or the infinities
+fn json_float(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value}")
+    } else {
+        "null".into()
+    }
+}
+
+fn json_array<T, F: Fn(&T) -> String>(data: &[T], fmt: F) -> String {
+    let items: Vec<_> = data.iter().map(fmt).collect();
+    format!("[{}]", items.join(","))
+}
+
+impl RawValue {
+    // Numbers are written at full precision: the formatting options don't apply here
+    pub fn to_json(&self) -> String {
+        match self {
+            RawValue::Char(val) => val.value.to_string(),
+            RawValue::Short(val) => val.value.to_string(),
+            RawValue::Long(val) => val.value.to_string(),
+            RawValue::Enum(val) => val.value.0.to_string(),
+            RawValue::Float(val) => json_float(val.value as f64),
+            RawValue::Double(val) => json_float(val.value),
+            RawValue::String(val) => json_string(&RawValue::string_lossy_trimmed(&val.value)),
+            RawValue::CharArray(val) => json_array(&val.value, |v| v.to_string()),
+            RawValue::ShortArray(val) => json_array(&val.value, |v| v.to_string()),
+            RawValue::LongArray(val) => json_array(&val.value, |v| v.to_string()),
+            RawValue::EnumArray(val) => json_array(&val.value, |v| v.0.to_string()),
+            RawValue::FloatArray(val) => json_array(&val.value, |v| json_float(*v as f64)),
+            RawValue::DoubleArray(val) => json_array(&val.value, |v| json_float(*v)),
+            RawValue::StringArray(val) => {
+                json_array(&val.value, |v| json_string(&RawValue::string_lossy_trimmed(v)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    pub fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+        }
+    }
+
+    pub fn is_equality(&self) -> bool {
+        matches!(self, Op::Eq | Op::Ne)
+    }
+}
+
+/*
+   Compares a scalar value against `target`, which is parsed according to the value's type.
+   Strings and enums (by index) only support == and !=. Arrays, and targets that can't be
+   parsed, never match.
+*/
+pub fn matches(value: &RawValue, op: Op, target: &str) -> bool {
+    match value {
+        RawValue::String(val) => {
+            op.is_equality() && op.compare(RawValue::string_lossy_trimmed(&val.value).as_str(), target)
+        }
+        RawValue::Enum(val) => {
+            op.is_equality() && target.parse::<u16>().is_ok_and(|t| op.compare(val.value.0, t))
+        }
+        _ => match (value.as_f64(), target.parse::<f64>()) {
+            (Some(v), Ok(t)) => op.compare(v, t),
+            _ => false,
+        },
+    }
+}
+
+// Element-wise, exact comparison, e.g. to tell whether a monitor update changed anything
+pub fn same_elements(left: &[ParsedValue], right: &[ParsedValue]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.matches(r, 0.0))
+}
+
+/*
+   Whether a value read back after a write holds what was written. Only the written elements are
+   checked, in case the server keeps a longer array.
+*/
+pub fn verify_written(value: &RawValue, written: &[ParsedValue], tolerance: f64) -> bool {
+    let read = value.elements();
+    read.len() >= written.len() && written.iter().zip(&read).all(|(w, r)| w.matches(r, tolerance))
+}
+
+// Base used to print integer values. Floats, strings and enums ignore it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+    Octal,
+}
+
+impl Radix {
+    // Negative values are printed in two's complement, as wide as their type
+    pub fn format<T: fmt::Display + fmt::LowerHex + fmt::Octal>(&self, value: T) -> String {
+        match self {
+            Radix::Decimal => format!("{value}"),
+            Radix::Hex => format!("{value:#x}"),
+            Radix::Octal => format!("{value:#o}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatOpts {
+    pub radix: Radix,
+    pub float: FloatFormat,
+    pub enum_as_number: bool,
+    // Print DBF_CHAR arrays as a single string, and DBF_CHAR scalars as a character
+    pub char_as_string: bool,
+    // Take the float precision from the record (PREC), when known, instead of from `float`
+    pub record_precision: bool,
+    // Scale numbers using SI prefixes (1.5M instead of 1500000). Overrides the radix
+    pub human: bool,
+    // Drop the trailing zeros of floats: 3.14 instead of 3.14000
+    pub trim_zeros: bool,
+    // Right-align array elements to the width of the widest one
+    pub align_columns: bool,
+    // Goes between output fields, and between array elements
+    pub separator: String,
+    // Fractional digits for timestamps: 0, 3, 6 or 9
+    pub stamp_digits: usize,
+    // Print timestamps in UTC, instead of local time
+    pub utc: bool,
+}
+
+impl FormatOpts {
+    pub fn format_int<T>(&self, value: T) -> String
+    where
+        T: fmt::Display + fmt::LowerHex + fmt::Octal + Into<f64>,
+    {
+        if self.human {
+            format_human(value.into(), self.float.precision())
+        } else {
+            self.radix.format(value)
+        }
+    }
+
+    pub fn format_float(&self, value: f64) -> String {
+        if self.human {
+            format_human(value, self.float.precision())
+        } else if self.trim_zeros {
+            trim_zeros(&self.float.format(value))
+        } else {
+            self.float.format(value)
+        }
+    }
+}
+
+impl Default for FormatOpts {
+    fn default() -> Self {
+        FormatOpts {
+            radix: Radix::default(),
+            float: FloatFormat::default(),
+            enum_as_number: false,
+            char_as_string: false,
+            record_precision: false,
+            align_columns: false,
+            human: false,
+            trim_zeros: false,
+            separator: DEFAULT_SEPARATOR.into(),
+            stamp_digits: DEFAULT_STAMP_DIGITS,
+            utc: false,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    NeverConnected,
+    PreviouslyConnected,
+    Connected,
+    Closed,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectionState::NeverConnected => "never connected",
+            ConnectionState::PreviouslyConnected => "previously connected",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Closed => "closed",
+        })
+    }
+}
+
+// What can be known about a connected channel without reading its value
+#[derive(Debug, Clone)]
+pub struct ChannelInfo {
+    pub name: String,
+    // Native type and capacity of the channel
+    pub field_type: FieldId,
+    pub element_count: usize,
+    pub state: ConnectionState,
+    pub host: String,
+    pub read_access: bool,
+    pub write_access: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArrayStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub count: usize,
+}
+
+impl ArrayStats {
+    fn from_values(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut stats: Option<ArrayStats> = None;
+        let mut sum = 0.0;
+        for value in values {
+            sum += value;
+            let st = stats.get_or_insert(ArrayStats { min: value, max: value, mean: 0.0, count: 0 });
+            st.min = st.min.min(value);
+            st.max = st.max.max(value);
+            st.count += 1;
+        }
+        stats.map(|st| ArrayStats { mean: sum / st.count as f64, ..st })
+    }
+
+    pub fn format(&self, opts: &FormatOpts) -> String {
+        format!(
+            "min={} max={} mean={} count={}",
+            opts.format_float(self.min),
+            opts.format_float(self.max),
+            opts.format_float(self.mean),
+            self.count
+        )
+    }
+}
+
+// All the limits are (low, high) pairs
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub display: (f64, f64),
+    // The writable range. Only the Ctrl metadata has it, not the Graphic one
+    pub control: Option<(f64, f64)>,
+    pub warning: (f64, f64),
+    pub alarm: (f64, f64),
+}
+
 #[derive(Debug)]
 pub struct Info {
     pub name: String,
+    // Capacity of the channel (NELM, for waveforms)
     pub elements: usize,
+    // Elements that came with the value (NORD, for waveforms): the ones worth printing
+    pub valid_elements: usize,
     pub value: RawValue,
+    pub status: AlarmCondition,
+    pub severity: AlarmSeverity,
+    // Native type of the channel, which may differ from the value's if a type was requested
+    pub field_type: &'static str,
+    pub enum_strings: Vec<String>,
+    // Only available when the Ctrl metadata has been requested
+    pub units: Option<String>,
+    pub precision: Option<i16>,
+    pub limits: Option<Limits>,
+    // Local time when the value was received
+    pub received: SystemTime,
 }
 
 impl Info {
     pub fn new(name: String, elements: usize, value: RawValue) -> Self {
+        let (status, severity) = value.get_alarm();
+        let field_type = value.type_name();
         Info {
             name,
             elements,
+            valid_elements: value.len(),
             value,
+            status,
+            severity,
+            field_type,
+            enum_strings: vec![],
+            units: None,
+            precision: None,
+            limits: None,
+            received: SystemTime::now(),
         }
     }
 
@@ -99,20 +798,658 @@ impl Info {
         self.elements == 1
     }
 
-    pub fn format_scalar(&self) -> String {
-        self.value.format_scalar()
+    // True if the value is going to be printed as a single string, instead of an array
+    pub fn is_char_string(&self, opts: &FormatOpts) -> bool {
+        opts.char_as_string && matches!(self.value, RawValue::CharArray(_))
+    }
+
+    // The formatting options, adjusted to the record's precision if requested
+    fn effective_opts<'a>(&self, opts: &'a FormatOpts) -> Cow<'a, FormatOpts> {
+        match self.precision {
+            Some(prec) if opts.record_precision => Cow::Owned(FormatOpts {
+                float: opts.float.with_precision(prec.max(0) as usize),
+                ..opts.clone()
+            }),
+            _ => Cow::Borrowed(opts),
+        }
+    }
+
+    pub fn format_scalar(&self, opts: &FormatOpts) -> String {
+        let opts = &*self.effective_opts(opts);
+        match &self.value {
+            RawValue::Enum(val) if !opts.enum_as_number => self
+                .enum_strings
+                .get(val.value.0 as usize)
+                .cloned()
+                .unwrap_or_else(|| self.value.format_scalar(opts)),
+            _ => self.value.format_scalar(opts),
+        }
+    }
+
+    /// Formats exactly `count` elements, truncating the array or padding it with zeroes
+    pub fn format_array(&self, count: usize, opts: &FormatOpts) -> String {
+        join_elements(self.format_elements(count, opts), opts)
+    }
+
+    /// One string per element, truncated or padded to `count` like `format_array`
+    pub fn format_elements(&self, count: usize, opts: &FormatOpts) -> Vec<String> {
+        let labels = if opts.enum_as_number { &[][..] } else { &self.enum_strings[..] };
+        self.value.format_elements_labeled(count, &self.effective_opts(opts), labels)
+    }
+
+    /// Elements `[start, end)`, padded like `format_elements`. A char string is sliced by chars
+    pub fn format_elements_range(&self, start: usize, end: usize, opts: &FormatOpts) -> Vec<String> {
+        let mut elements = self.format_elements(end, opts);
+        if self.is_char_string(opts) {
+            elements = elements.iter().map(|s| s.chars().skip(start).collect()).collect();
+        } else {
+            elements.drain(..start.min(elements.len()));
+        }
+        elements
+    }
+
+    pub fn format_array_range(&self, start: usize, end: usize, opts: &FormatOpts) -> String {
+        join_elements(self.format_elements_range(start, end, opts), opts)
+    }
+
+    /// Statistics over every element that came with the value, at the record's precision if asked
+    pub fn format_stats(&self, opts: &FormatOpts) -> Option<String> {
+        let stats = self.value.array_stats()?;
+        Some(stats.format(&self.effective_opts(opts)))
+    }
+
+    /// Formats every element that came with the value, without any padding
+    pub fn format_array_full(&self, opts: &FormatOpts) -> String {
+        self.format_array(self.value.len(), opts)
+    }
+
+    /// The limits, named after the record fields they come from. Empty if there's no Ctrl or
+    /// Graphic data
+    pub fn format_limits(&self) -> String {
+        let Some(limits) = &self.limits else {
+            return String::new();
+        };
+        let control = limits.control.map(|(low, high)| [("DRVL", low), ("DRVH", high)]);
+        [("LOPR", limits.display.0), ("HOPR", limits.display.1)]
+            .iter()
+            .chain(control.iter().flatten())
+            .chain(&[
+                ("LOLO", limits.alarm.0),
+                ("LOW", limits.warning.0),
+                ("HIGH", limits.warning.1),
+                ("HIHI", limits.alarm.1),
+            ])
+            .map(|(field, value)| format!("{field}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Alarm status and severity, using the same names as the EPICS base tools
+    pub fn format_alarm(&self) -> String {
+        format!("{} {}", condition_name(self.status), severity_name(self.severity))
+    }
+
+    // Scalar enums are written as their label, unless asked for numbers
+    pub fn to_json(&self, opts: &FormatOpts) -> String {
+        let value = match &self.value {
+            RawValue::Enum(_) if !opts.enum_as_number && !self.enum_strings.is_empty() => {
+                json_string(&self.format_scalar(opts))
+            }
+            value => value.to_json(),
+        };
+
+        let fields = [
+            ("name", json_string(&self.name)),
+            ("type", json_string(self.field_type)),
+            ("count", self.valid_elements.to_string()),
+            ("value", value),
+            ("timestamp", json_string(&self.format_stamp(opts))),
+            ("status", json_string(condition_name(self.status))),
+            ("severity", json_string(severity_name(self.severity))),
+        ];
+        let fields: Vec<_> = fields.iter().map(|(key, val)| format!("\"{key}\":{val}")).collect();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    pub fn format_stamp(&self, opts: &FormatOpts) -> String {
+        format_system_time(self.value.get_stamp().to_system(), opts)
+    }
+
+    /*
+       `reference` is the time that Incremental and Relative stamps are computed against: the
+       previous sample for the former, and the start of the program for the latter. See
+       TimestampFormatter, which keeps track of those.
+    */
+    pub fn format_stamp_as(
+        &self,
+        kind: TimestampKind,
+        reference: SystemTime,
+        opts: &FormatOpts,
+    ) -> Option<String> {
+        match kind {
+            TimestampKind::CAServer => Some(self.format_stamp(opts)),
+            TimestampKind::CAClient => Some(format_system_time(self.received, opts)),
+            TimestampKind::Incremental
+            | TimestampKind::IncrementalByChannel
+            | TimestampKind::Relative => {
+                let delta = time_delta(reference, self.value.get_stamp().to_system());
+                Some(format!("{delta:.0$}", opts.stamp_digits))
+            }
+            TimestampKind::No => None,
+        }
+    }
+}
+
+pub fn field_type_name(id: FieldId) -> &'static str {
+    match id {
+        FieldId::String => "DBF_STRING",
+        FieldId::Short => "DBF_SHORT",
+        FieldId::Float => "DBF_FLOAT",
+        FieldId::Enum => "DBF_ENUM",
+        FieldId::Char => "DBF_CHAR",
+        FieldId::Long => "DBF_LONG",
+        FieldId::Double => "DBF_DOUBLE",
+    }
+}
+
+pub fn severity_name(severity: AlarmSeverity) -> &'static str {
+    match severity {
+        AlarmSeverity::None => "NO_ALARM",
+        AlarmSeverity::Minor => "MINOR",
+        AlarmSeverity::Major => "MAJOR",
+        AlarmSeverity::Invalid => "INVALID",
+    }
+}
+
+pub fn condition_name(condition: AlarmCondition) -> &'static str {
+    match condition {
+        AlarmCondition::None => "NO_ALARM",
+        AlarmCondition::Read => "READ",
+        AlarmCondition::Write => "WRITE",
+        AlarmCondition::HiHi => "HIHI",
+        AlarmCondition::High => "HIGH",
+        AlarmCondition::LoLo => "LOLO",
+        AlarmCondition::Low => "LOW",
+        AlarmCondition::State => "STATE",
+        AlarmCondition::Cos => "COS",
+        AlarmCondition::Comm => "COMM",
+        AlarmCondition::Timeout => "TIMEOUT",
+        AlarmCondition::HwLimit => "HWLIMIT",
+        AlarmCondition::Calc => "CALC",
+        AlarmCondition::Scan => "SCAN",
+        AlarmCondition::Link => "LINK",
+        AlarmCondition::Soft => "SOFT",
+        AlarmCondition::BadSub => "BAD_SUB",
+        AlarmCondition::Udf => "UDF",
+        AlarmCondition::Disable => "DISABLE",
+        AlarmCondition::Simm => "SIMM",
+        AlarmCondition::ReadAccess => "READ_ACCESS",
+        AlarmCondition::WriteAccess => "WRITE_ACCESS",
+    }
+}
+
+// Source of the current time, so that the Relative stamps can be made deterministic
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// Always returns the same time
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/*
+   Formats the time stamps of a sequence of samples. The incremental kinds need to remember the
+   previous sample: the last one from any channel for Incremental, and the last one from the
+   same channel for IncrementalByChannel. The first sample of each sequence shows a delta of 0.
+*/
+pub struct TimestampFormatter {
+    kind: TimestampKind,
+    // Start of the program, for Relative
+    start: SystemTime,
+    last: Option<SystemTime>,
+    last_by_channel: HashMap<String, SystemTime>,
+}
+
+impl TimestampFormatter {
+    // Relative stamps are computed against the time on `clock` when the formatter is created
+    pub fn new(kind: TimestampKind, clock: &impl Clock) -> Self {
+        TimestampFormatter {
+            kind,
+            start: clock.now(),
+            last: None,
+            last_by_channel: HashMap::new(),
+        }
+    }
+
+    pub fn format(&mut self, info: &Info, opts: &FormatOpts) -> Option<String> {
+        let stamp = info.value.get_stamp().to_system();
+        let reference = match self.kind {
+            TimestampKind::Incremental => self.last.replace(stamp).unwrap_or(stamp),
+            TimestampKind::IncrementalByChannel => self
+                .last_by_channel
+                .insert(info.name.clone(), stamp)
+                .unwrap_or(stamp),
+            _ => self.start,
+        };
+        info.format_stamp_as(self.kind, reference, opts)
+    }
+}
+
+// Uses the timestamp digits and time zone from `opts`
+pub fn format_system_time(time: SystemTime, opts: &FormatOpts) -> String {
+    let fmt = match opts.stamp_digits {
+        0 => "%F %T",
+        3 => "%F %T%.3f",
+        9 => "%F %T%.9f",
+        _ => "%F %T%.6f",
+    };
+    if opts.utc {
+        let stamp: DateTime<Utc> = time.into();
+        format!("{}", stamp.format(fmt))
+    } else {
+        let stamp: DateTime<Local> = time.into();
+        format!("{}", stamp.format(fmt))
+    }
+}
+
+// Seconds from `from` to `to`, negative if `to` happened earlier
+pub fn time_delta(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(delta) => delta.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{time_array, time_value};
+
+    fn double(value: f64) -> Info {
+        Info::new("pv".into(), 1, RawValue::Double(time_value(value)))
+    }
+
+    #[test]
+    fn scalars_of_every_type() {
+        assert_eq!(RawValue::Char(time_value(65)).format_scalar(&FormatOpts::default()), "65");
+        assert_eq!(RawValue::Short(time_value(-3)).format_scalar(&FormatOpts::default()), "-3");
+        assert_eq!(RawValue::Long(time_value(100_000)).format_scalar(&FormatOpts::default()), "100000");
+        assert_eq!(RawValue::Float(time_value(0.5)).format_scalar(&FormatOpts::default()), "0.50000");
+        assert_eq!(RawValue::Enum(time_value(EpicsEnum(2))).format_scalar(&FormatOpts::default()), "2");
+    }
+
+    #[test]
+    fn arrays_are_padded() {
+        let longs = RawValue::LongArray(time_array(&[1, -2, 3]));
+        assert_eq!(longs.format_array(3, &FormatOpts::default()), "1 -2 3");
+        assert_eq!(longs.format_array(5, &FormatOpts::default()), "1 -2 3 0 0");
+        let doubles = RawValue::DoubleArray(time_array(&[0.5]));
+        assert_eq!(doubles.format_array(2, &FormatOpts::default()), "0.50000 0");
+    }
+
+    #[test]
+    fn full_arrays_are_not_padded() {
+        let info = Info::new("pv".into(), 5, RawValue::ShortArray(time_array(&[7, 8, 9])));
+        assert_eq!(info.format_array_full(&FormatOpts::default()), "7 8 9");
+        // Unlike format_array, which stops at the count asked for
+        assert_eq!(info.value.format_array(2, &FormatOpts::default()), "7 8");
+    }
+
+    #[test]
+    fn enums_are_printed_as_their_label() {
+        let mut info = Info::new("pv".into(), 1, RawValue::Enum(time_value(EpicsEnum(1))));
+        info.enum_strings = vec!["Off".into(), "On".into()];
+        assert_eq!(info.format_scalar(&FormatOpts::default()), "On");
+        let numbers = FormatOpts { enum_as_number: true, ..Default::default() };
+        assert_eq!(info.format_scalar(&numbers), "1");
+        // Out of range indices fall back to the number
+        info.value = RawValue::Enum(time_value(EpicsEnum(5)));
+        assert_eq!(info.format_scalar(&FormatOpts::default()), "5");
+    }
+
+    #[test]
+    fn hex_and_octal_integers() {
+        assert_eq!(Radix::Hex.format(255), "0xff");
+        assert_eq!(Radix::Octal.format(8), "0o10");
+        // Two's complement, as wide as the type
+        assert_eq!(Radix::Hex.format(-1i32), "0xffffffff");
+        assert_eq!(Radix::Hex.format(-2i16), "0xfffe");
+        let opts = FormatOpts { radix: Radix::Hex, ..Default::default() };
+        assert_eq!(RawValue::Long(time_value(-16)).format_scalar(&opts), "0xfffffff0");
+    }
+
+    #[test]
+    fn alarms_come_from_the_value() {
+        let mut value = time_value(1.0);
+        value.alarm = epics_ca::types::Alarm { condition: AlarmCondition::HiHi, severity: AlarmSeverity::Major };
+        let info = Info::new("pv".into(), 1, RawValue::Double(value));
+        assert_eq!(info.status, AlarmCondition::HiHi);
+        assert_eq!(info.format_alarm(), "HIHI MAJOR");
+        assert_eq!(double(0.0).format_alarm(), "NO_ALARM NO_ALARM");
+    }
+
+    #[test]
+    fn json_objects() {
+        let info = Info::new("pv".into(), 1, RawValue::Double(time_value(1.5)));
+        let stamp = info.format_stamp(&FormatOpts::default());
+        assert_eq!(
+            info.to_json(&FormatOpts::default()),
+            format!(r#"{{"name":"pv","type":"DBF_DOUBLE","count":1,"value":1.5,"timestamp":"{stamp}","#)
+                + r#""status":"NO_ALARM","severity":"NO_ALARM"}"#
+        );
+        assert_eq!(RawValue::Double(time_value(f64::NAN)).to_json(), "null");
+        assert_eq!(RawValue::LongArray(time_array(&[1, 2])).to_json(), "[1,2]");
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+    }
+
+    #[test]
+    fn char_arrays_as_strings() {
+        assert_eq!(char_string(b"hello\0world"), "hello");
+        assert_eq!(char_string(b"no nul"), "no nul");
+        let opts = FormatOpts { char_as_string: true, ..Default::default() };
+        let info = Info::new("pv".into(), 8, RawValue::CharArray(time_array(b"abc\0")));
+        assert_eq!(info.format_array_full(&opts), "abc");
+    }
+
+    #[test]
+    fn record_precision_applies_when_known() {
+        let opts = FormatOpts { record_precision: true, ..Default::default() };
+        let mut info = double(1.23456);
+        assert_eq!(info.format_scalar(&opts), "1.23456");
+        info.precision = Some(2);
+        assert_eq!(info.format_scalar(&opts), "1.23");
+        // -p given: the record's precision is ignored
+        assert_eq!(info.format_scalar(&FormatOpts::default()), "1.23456");
+    }
+
+    #[test]
+    fn conditions_on_numbers_and_strings() {
+        let value = RawValue::Double(time_value(2.5));
+        assert!(matches(&value, Op::Ge, "2.5"));
+        assert!(matches(&value, Op::Ge, "1"));
+        assert!(!matches(&value, Op::Ge, "3"));
+        assert!(!matches(&value, Op::Ge, "high"));
+        let string = RawValue::String(time_value(EpicsString::from_cstr(c"Open").unwrap()));
+        assert!(matches(&string, Op::Eq, "Open"));
+        assert!(!matches(&string, Op::Eq, "Closed"));
+        assert!(matches(&string, Op::Ne, "Closed"));
+        assert!(!matches(&string, Op::Ne, "Open"));
+        // Strings can only be compared for equality
+        assert!(!matches(&string, Op::Ge, "Open"));
+    }
+
+    #[test]
+    fn enums_only_support_equality() {
+        let value = RawValue::Enum(time_value(EpicsEnum(1)));
+        assert!(matches(&value, Op::Eq, "1"));
+        assert!(matches(&value, Op::Ne, "0"));
+        assert!(!matches(&value, Op::Gt, "0"));
+    }
+
+    #[test]
+    fn arrays_never_match() {
+        let value = RawValue::DoubleArray(time_array(&[1.0, 2.0]));
+        assert!(!matches(&value, Op::Eq, "1"));
+    }
+
+    #[test]
+    fn same_elements_needs_the_same_length() {
+        let left = [ParsedValue::Long(1), ParsedValue::Long(2)];
+        assert!(same_elements(&left, &left));
+        assert!(!same_elements(&left, &left[..1]));
+        assert!(!same_elements(&left, &[ParsedValue::Long(1), ParsedValue::Long(3)]));
+    }
+
+    #[test]
+    fn aligned_columns() {
+        let opts = FormatOpts { align_columns: true, ..Default::default() };
+        let value = RawValue::LongArray(time_array(&[1, -20, 300]));
+        assert_eq!(value.format_array(3, &opts), "  1 -20 300");
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(field_type_name(FieldId::Short), "DBF_SHORT");
+        assert_eq!(RawValue::StringArray(time_array(&[EpicsString::default()])).type_name(), "DBF_STRING");
+        assert_eq!(double(0.0).field_type, "DBF_DOUBLE");
+    }
+
+    #[test]
+    fn enum_arrays_use_the_labels_unless_asked_for_numbers() {
+        let mut info = Info::new("pv".into(), 3, RawValue::EnumArray(time_array(&[EpicsEnum(1), EpicsEnum(0), EpicsEnum(7)])));
+        info.enum_strings = vec!["Off".into(), "On".into()];
+        assert_eq!(info.format_array_full(&FormatOpts::default()), "On Off 7");
+        let numbers = FormatOpts { enum_as_number: true, ..Default::default() };
+        assert_eq!(info.format_array_full(&numbers), "1 0 7");
+    }
+
+    #[test]
+    fn values_are_parsed_in_range() {
+        assert!(matches!(parse_value(FieldId::Char, "255", &[]), Ok(ParsedValue::Char(255))));
+        assert!(parse_value(FieldId::Char, "256", &[]).is_err());
+        assert!(matches!(parse_value(FieldId::Short, "-0x10", &[]), Ok(ParsedValue::Short(-16))));
+        assert!(parse_value(FieldId::Short, "40000", &[]).is_err());
+        assert!(matches!(parse_value(FieldId::Long, "0o17", &[]), Ok(ParsedValue::Long(15))));
+        assert!(matches!(parse_value(FieldId::Double, " 2.5 ", &[]), Ok(ParsedValue::Double(v)) if v == 2.5));
+        // Fine for a double, too big for a float
+        assert!(parse_value(FieldId::Float, "1e39", &[]).is_err());
+        assert!(parse_value(FieldId::Float, "inf", &[]).is_ok());
+        assert!(parse_value(FieldId::Long, "twelve", &[]).is_err());
+    }
+
+    #[test]
+    fn strings_must_fit() {
+        assert!(parse_value(FieldId::String, &"x".repeat(39), &[]).is_ok());
+        assert!(parse_value(FieldId::String, &"x".repeat(40), &[]).is_err());
+    }
+
+    #[test]
+    fn truncated_strings_fit_and_keep_whole_characters() {
+        assert_eq!(truncate_epics_string("short"), "short");
+        assert_eq!(truncate_epics_string(&"x".repeat(50)), "x".repeat(MAX_STRING_SIZE - 1));
+        // The 2-byte é at bytes 38 and 39 doesn't fit, so it goes whole
+        let accented = format!("{}é", "x".repeat(38));
+        assert_eq!(truncate_epics_string(&accented), "x".repeat(38));
+    }
+
+    #[test]
+    fn limits_are_named_after_their_fields() {
+        let mut info = double(0.0);
+        assert_eq!(info.format_limits(), "");
+        info.limits = Some(Limits { display: (0.0, 10.0), control: Some((-1.0, 11.0)), warning: (1.0, 9.0), alarm: (0.5, 9.5) });
+        assert_eq!(info.format_limits(), "LOPR=0 HOPR=10 DRVL=-1 DRVH=11 LOLO=0.5 LOW=1 HIGH=9 HIHI=9.5");
+        // The Graphic metadata has no control limits
+        info.limits = Some(Limits { display: (0.0, 10.0), control: None, warning: (1.0, 9.0), alarm: (0.5, 9.5) });
+        assert_eq!(info.format_limits(), "LOPR=0 HOPR=10 LOLO=0.5 LOW=1 HIGH=9 HIHI=9.5");
+    }
+
+    #[test]
+    fn si_prefixes() {
+        assert_eq!(format_human(1500000.0, 2), "1.50M");
+        assert_eq!(format_human(-2500.0, 1), "-2.5k");
+        assert_eq!(format_human(0.0015, 2), "1.50m");
+        assert_eq!(format_human(12.0, 0), "12");
+        // Rounds up into the next prefix
+        assert_eq!(format_human(999999.9, 2), "1.00M");
+        assert_eq!(format_human(0.0, 2), "0.00");
+        let opts = FormatOpts { human: true, float: FloatFormat::Fixed(1), ..Default::default() };
+        assert_eq!(RawValue::Long(time_value(1_500_000)).format_scalar(&opts), "1.5M");
+    }
+
+    #[test]
+    fn stamp_digits() {
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::new(1, 123_456_789);
+        let seconds = DateTime::<Local>::from(time).format("%F %T").to_string();
+        let opts = |stamp_digits| FormatOpts { stamp_digits, ..Default::default() };
+        assert_eq!(format_system_time(time, &opts(0)), seconds);
+        assert_eq!(format_system_time(time, &opts(3)), format!("{seconds}.123"));
+        assert_eq!(format_system_time(time, &opts(6)), format!("{seconds}.123456"));
+        assert_eq!(format_system_time(time, &opts(9)), format!("{seconds}.123456789"));
+    }
+
+    #[test]
+    fn stamps_in_utc_or_local_time() {
+        let time = SystemTime::UNIX_EPOCH;
+        let utc = FormatOpts { stamp_digits: 0, utc: true, ..Default::default() };
+        assert_eq!(format_system_time(time, &utc), "1970-01-01 00:00:00");
+        let local: DateTime<Local> = time.into();
+        let opts = FormatOpts { utc: false, ..utc };
+        assert_eq!(format_system_time(time, &opts), local.format("%F %T").to_string());
+    }
+
+    #[test]
+    fn char_scalars_as_numbers_or_characters() {
+        let value = RawValue::Char(time_value(b' '));
+        assert_eq!(value.format_scalar(&FormatOpts::default()), "32");
+        let chars = FormatOpts { char_as_string: true, ..Default::default() };
+        assert_eq!(value.format_scalar(&chars), " ");
+        assert_eq!(RawValue::Char(time_value(0xff)).format_scalar(&chars), "\\xff");
+        let hex = FormatOpts { radix: Radix::Hex, ..Default::default() };
+        assert_eq!(RawValue::Char(time_value(0xff)).format_scalar(&hex), "0xff");
+    }
+
+    #[test]
+    fn only_the_valid_elements_are_printed() {
+        // A waveform with NELM = 10 and NORD = 3
+        let info = Info::new("pv".into(), 10, RawValue::DoubleArray(time_array(&[1.0, 2.0, 3.0])));
+        assert_eq!((info.elements, info.valid_elements), (10, 3));
+        assert!(!info.is_scalar());
+        let opts = FormatOpts { float: FloatFormat::Fixed(0), ..Default::default() };
+        assert_eq!(info.format_array_range(0, info.valid_elements, &opts), "1 2 3");
+    }
+
+    // A sample of `name` stamped `sec` seconds (and `msec` milliseconds) past the EPICS epoch
+    fn sample(name: &str, sec: u32, msec: u32) -> Info {
+        let mut value = time_value(0.0);
+        let nsec = msec * 1_000_000;
+        value.stamp = EpicsTimeStamp(epics_ca_sys::epicsTimeStamp { secPastEpoch: sec, nsec });
+        Info::new(name.into(), 1, RawValue::Double(value))
+    }
+
+    fn stamps(kind: TimestampKind, clock: &impl Clock, samples: &[Info]) -> Vec<Option<String>> {
+        let opts = FormatOpts { stamp_digits: 3, ..Default::default() };
+        let mut stamps = TimestampFormatter::new(kind, clock);
+        samples.iter().map(|info| stamps.format(info, &opts)).collect()
+    }
+
+    #[test]
+    fn incremental_stamps_follow_the_previous_sample() {
+        let samples = [sample("a", 100, 0), sample("a", 100, 500), sample("b", 102, 0)];
+        let clock = FixedClock(SystemTime::UNIX_EPOCH);
+        assert_eq!(
+            stamps(TimestampKind::Incremental, &clock, &samples),
+            [Some("0.000".into()), Some("0.500".into()), Some("1.500".into())]
+        );
+    }
+
+    #[test]
+    fn incremental_stamps_by_channel() {
+        let samples = [sample("a", 100, 0), sample("b", 101, 0), sample("a", 103, 250)];
+        let clock = FixedClock(SystemTime::UNIX_EPOCH);
+        assert_eq!(
+            stamps(TimestampKind::IncrementalByChannel, &clock, &samples),
+            [Some("0.000".into()), Some("0.000".into()), Some("3.250".into())]
+        );
+    }
+
+    #[test]
+    fn relative_stamps_count_from_the_start() {
+        let start = sample("a", 100, 0).value.get_stamp().to_system();
+        let samples = [sample("a", 100, 250), sample("b", 99, 0)];
+        assert_eq!(
+            stamps(TimestampKind::Relative, &FixedClock(start), &samples),
+            [Some("0.250".into()), Some("-1.000".into())]
+        );
+        assert_eq!(stamps(TimestampKind::No, &FixedClock(start), &samples), [None, None]);
+    }
+
+    #[test]
+    fn one_set_of_options_for_scalars_and_arrays() {
+        let opts = FormatOpts { radix: Radix::Hex, float: FloatFormat::Fixed(2), separator: ",".into(), ..Default::default() };
+        assert_eq!(RawValue::Long(time_value(255)).format_scalar(&opts), "0xff");
+        assert_eq!(RawValue::LongArray(time_array(&[10, 255])).format_array(2, &opts), "0xa,0xff");
+        assert_eq!(RawValue::DoubleArray(time_array(&[0.5, 2.0])).format_array(2, &opts), "0.50,2.00");
+    }
+
+    #[test]
+    fn strings_end_at_the_first_nul() {
+        let mut data = [0; MAX_STRING_SIZE];
+        for (c, b) in data.iter_mut().zip(b"hi\0junk") {
+            *c = *b as _;
+        }
+        let s = EpicsString::from_array(data).unwrap();
+        assert_eq!(RawValue::string_lossy_trimmed(&s), "hi");
+    }
+
+    #[test]
+    fn statistics_of_numeric_arrays() {
+        let stats = RawValue::LongArray(time_array(&[4, -2, 7, 3])).array_stats().unwrap();
+        assert_eq!((stats.min, stats.max, stats.mean, stats.count), (-2.0, 7.0, 3.0, 4));
+        let opts = FormatOpts { float: FloatFormat::Fixed(1), ..Default::default() };
+        assert_eq!(stats.format(&opts), "min=-2.0 max=7.0 mean=3.0 count=4");
+        assert!(RawValue::LongArray(time_array::<i32>(&[])).array_stats().is_none());
+        assert!(RawValue::Long(time_value(1)).array_stats().is_none());
+    }
+
+    #[test]
+    fn verify_written_checks_only_the_written_elements() {
+        let read = RawValue::DoubleArray(time_array(&[1.0, 2.0004, 9.0]));
+        let written = [ParsedValue::Double(1.0), ParsedValue::Double(2.0)];
+        assert!(verify_written(&read, &written, 0.001));
+        assert!(!verify_written(&read, &written, 0.0));
+        assert!(!verify_written(&RawValue::Double(time_value(1.0)), &written, 0.001));
     }
 
-    pub fn format_array(&self, count: usize) -> String {
-        self.value.format_array(count)
+    #[test]
+    fn alarm_names_from_the_raw_codes() {
+        let severities: Vec<_> = (0..5).map(|raw| AlarmSeverity::try_from_raw(raw).map(severity_name)).collect();
+        assert_eq!(severities, [Some("NO_ALARM"), Some("MINOR"), Some("MAJOR"), Some("INVALID"), None]);
+        assert_eq!(AlarmCondition::try_from_raw(3).map(condition_name), Some("HIHI"));
+        assert_eq!(AlarmCondition::try_from_raw(17).map(condition_name), Some("UDF"));
+        assert_eq!(AlarmCondition::try_from_raw(1000), None);
     }
 
-    pub fn format_array_full(&self) -> String {
-        self.format_array(self.elements)
+    #[test]
+    fn hexdump_lines() {
+        let data: Vec<u8> = (0..20).map(|i| b'A' + i).chain([0, b'\n']).collect();
+        let lines = hexdump(&data, 0);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "00000000  41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|");
+        assert_eq!(lines[1], format!("00000010  51 52 53 54 00 0a{}  |QRST..|", " ".repeat(30)));
+        // Offsets count from the start of the array, not of the slice shown
+        let array = RawValue::CharArray(time_array(&data));
+        assert!(array.hexdump(18, 100).unwrap()[0].starts_with("00000012  53 54 00 0a  "));
+        assert!(RawValue::Long(time_value(1)).hexdump(0, 1).is_none());
     }
 
-    pub fn format_stamp(&self) -> String {
-        let stamp: DateTime<Local> = self.value.get_stamp().to_system().into();
-        format!("{}", stamp.format("%F %T%.6f"))
+    #[test]
+    fn enum_states_by_label_or_index() {
+        let labels = ["Off".to_string(), "On".to_string()];
+        let state = |s| match parse_value(FieldId::Enum, s, &labels) {
+            Ok(ParsedValue::Enum(EpicsEnum(idx))) => Ok(idx),
+            Ok(other) => panic!("{other:?}"),
+            Err(e) => Err(e.to_string()),
+        };
+        assert_eq!(state("On"), Ok(1));
+        assert_eq!(state("0"), Ok(0));
+        assert_eq!(state("Standby"), Err("'Standby' is not a valid state, expected an index or one of: Off, On".into()));
+        assert_eq!(state("2"), Err("'2' is not a valid state: there are only 2".into()));
     }
 }
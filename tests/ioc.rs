@@ -0,0 +1,198 @@
+/*
+   These read the records in tests/ioc.db, so they need an IOC serving them:
+
+       softIoc -d tests/ioc.db
+       cargo test -- --ignored
+*/
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use epics_ca::{types::{EpicsEnum, EventMask, FieldId}, Context};
+use epics_tools::{
+    build_context, config::{ca_env::CaEnv, RETRY_BACKOFF_MS}, connect_with_retry, describe,
+    get_channels, get_pv, get_pvs, grab_full_info, grab_graphic_info, grab_info_as, monitor_pv, put_pv,
+    types::{ConnectionState, Info, RawValue}, wait_connect,
+};
+use futures::StreamExt;
+use tokio::time::timeout;
+
+const TIMEOUT_MS: u64 = 2000;
+
+async fn read(name: &str) -> Info {
+    read_as(name, None).await
+}
+
+async fn read_as(name: &str, requested: Option<FieldId>) -> Info {
+    let ctx = Context::new().unwrap();
+    let (mut channels, _) = get_channels(&ctx, &[name.to_string()]).unwrap();
+    wait_connect(&mut channels, TIMEOUT_MS).await.unwrap();
+    grab_info_as(&mut channels[0], requested).await.unwrap()
+}
+
+#[tokio::test]
+#[ignore]
+async fn char_arrays() {
+    let info = read("epics-tools:test:chars").await;
+    assert_eq!(info.elements, 16);
+    assert!(matches!(info.value, RawValue::CharArray(val) if val.value.starts_with(b"hi\0")));
+}
+
+#[tokio::test]
+#[ignore]
+async fn enum_arrays() {
+    let info = read("epics-tools:test:enums").await;
+    assert!(matches!(info.value, RawValue::EnumArray(val) if val.value[..3] == [EpicsEnum(1), EpicsEnum(0), EpicsEnum(2)]));
+}
+
+#[tokio::test]
+#[ignore]
+async fn missing_pvs_fail_after_the_retries() {
+    let ctx = Context::new().unwrap();
+    let (mut channels, _) = get_channels(&ctx, &["epics-tools:test:missing".to_string()]).unwrap();
+    let start = Instant::now();
+    assert!(connect_with_retry(&mut channels[0], 100, 2).await.is_err());
+    // Three attempts, with a pause before each retry that doubles the previous one
+    assert!(start.elapsed() >= Duration::from_millis(3 * 100 + 3 * RETRY_BACKOFF_MS));
+}
+
+#[test]
+#[ignore]
+fn rcaget_prints_the_pvs_read_when_others_fail() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rcaget"))
+        .args(["-w", "0.5", "epics-tools:test:chars", "epics-tools:test:missing"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("epics-tools:test:chars "), "{stdout}");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("epics-tools:test:missing: "));
+}
+
+#[test]
+#[ignore]
+fn rcawait_exits_with_2_when_the_condition_times_out() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rcawait"))
+        .args(["-w", "0.5", "epics-tools:test:number", "<", "0"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Timed out waiting for the condition\n");
+}
+
+#[tokio::test]
+#[ignore]
+async fn get_pv_reads_a_single_pv() {
+    let ctx = Context::new().unwrap();
+    let info = get_pv(&ctx, "epics-tools:test:chars", Duration::from_millis(TIMEOUT_MS)).await.unwrap();
+    assert_eq!(info.name, "epics-tools:test:chars");
+    assert!(matches!(info.value, RawValue::CharArray(_)));
+}
+
+#[tokio::test]
+#[ignore]
+async fn get_pvs_keeps_the_order_of_the_names() {
+    let ctx = Context::new().unwrap();
+    let names = ["epics-tools:test:enums".to_string(), "epics-tools:test:chars".to_string()];
+    let info = get_pvs(&ctx, &names, Duration::from_millis(TIMEOUT_MS)).await.unwrap();
+    let read: Vec<_> = info.iter().map(|info| info.name.clone()).collect();
+    assert_eq!(read, names);
+    // A single missing PV fails the whole call
+    let names = [names[0].clone(), "epics-tools:test:missing".to_string()];
+    assert!(get_pvs(&ctx, &names, Duration::from_millis(500)).await.is_err());
+}
+
+#[tokio::test]
+#[ignore]
+async fn put_pv_writes_the_native_type() {
+    let ctx = Context::new().unwrap();
+    let timeout = Duration::from_millis(TIMEOUT_MS);
+    put_pv(&ctx, "epics-tools:test:setpoint", "2.5", timeout).await.unwrap();
+    let info = get_pv(&ctx, "epics-tools:test:setpoint", timeout).await.unwrap();
+    assert!(matches!(info.value, RawValue::Double(val) if val.value == 2.5));
+    assert!(put_pv(&ctx, "epics-tools:test:setpoint", "high", timeout).await.is_err());
+}
+
+#[tokio::test]
+#[ignore]
+async fn monitor_pv_starts_with_the_current_value() {
+    let ctx = Context::new().unwrap();
+    let updates = monitor_pv(&ctx, "epics-tools:test:chars", EventMask::VALUE).await.unwrap();
+    tokio::pin!(updates);
+    let first = timeout(Duration::from_millis(TIMEOUT_MS), updates.next()).await.unwrap();
+    let info = first.unwrap().unwrap();
+    assert!(matches!(info.value, RawValue::CharArray(val) if val.value.starts_with(b"hi\0")));
+}
+
+#[tokio::test]
+#[ignore]
+async fn scalars_of_every_type() {
+    let number = "epics-tools:test:number";
+    assert!(matches!(read(number).await.value, RawValue::Double(val) if val.value == 65.0));
+    let short = read_as(number, Some(FieldId::Short)).await;
+    assert!(matches!(short.value, RawValue::Short(val) if val.value == 65));
+    let float = read_as(number, Some(FieldId::Float)).await;
+    assert!(matches!(float.value, RawValue::Float(val) if val.value == 65.0));
+    let char = read_as(number, Some(FieldId::Char)).await;
+    assert!(matches!(char.value, RawValue::Char(val) if val.value == b'A'));
+    let long = read_as(number, Some(FieldId::Long)).await;
+    assert!(matches!(long.value, RawValue::Long(val) if val.value == 65));
+    let string = read_as(number, Some(FieldId::String)).await;
+    assert!(matches!(&string.value, RawValue::String(val) if val.value.to_string_lossy().starts_with("65")));
+    // Only enums can be read as enums
+    let state = read_as("epics-tools:test:state", Some(FieldId::Enum)).await;
+    assert!(matches!(state.value, RawValue::Enum(val) if val.value == EpicsEnum(1)));
+    assert_eq!(state.enum_strings[..2], ["Off", "On"]);
+}
+
+#[tokio::test]
+#[ignore]
+async fn build_context_uses_the_settings() {
+    // The port the IOC is on, so that the other tests running alongside aren't disturbed
+    let server_port = Some(CaEnv::from_env().server_port.unwrap_or(5064));
+    let ctx = build_context(&CaEnv { server_port, ..Default::default() }).unwrap();
+    let (mut channels, _) = get_channels(&ctx, &["epics-tools:test:number".to_string()]).unwrap();
+    wait_connect(&mut channels, TIMEOUT_MS).await.unwrap();
+    let info = grab_info_as(&mut channels[0], None).await.unwrap();
+    assert!(matches!(info.value, RawValue::Double(val) if val.value == 65.0));
+}
+
+#[tokio::test]
+#[ignore]
+async fn graphic_info_has_no_control_limits() {
+    let ctx = Context::new().unwrap();
+    let (mut channels, _) = get_channels(&ctx, &["epics-tools:test:limits".to_string()]).unwrap();
+    wait_connect(&mut channels, TIMEOUT_MS).await.unwrap();
+
+    let graphic = grab_graphic_info(&mut channels[0]).await.unwrap();
+    assert_eq!(graphic.units.as_deref(), Some("mm"));
+    assert_eq!(graphic.precision, Some(2));
+    let limits = graphic.limits.unwrap();
+    assert_eq!(limits.display, (0.0, 10.0));
+    assert_eq!(limits.control, None);
+    assert_eq!(limits.warning, (1.0, 9.0));
+    assert_eq!(limits.alarm, (0.5, 9.5));
+
+    let ctrl = grab_full_info(&mut channels[0]).await.unwrap();
+    let limits = ctrl.limits.unwrap();
+    assert_eq!(limits.display, (0.0, 10.0));
+    assert_eq!(limits.control, Some((-1.0, 11.0)));
+}
+
+#[tokio::test]
+#[ignore]
+async fn describe_needs_a_connected_channel() {
+    let ctx = Context::new().unwrap();
+    let names = ["epics-tools:test:chars".to_string(), "epics-tools:test:missing".to_string()];
+    let (mut channels, _) = get_channels(&ctx, &names).unwrap();
+    assert!(describe(&channels[1]).is_err());
+
+    wait_connect(&mut channels[..1], TIMEOUT_MS).await.unwrap();
+    let desc = describe(&channels[0]).unwrap();
+    assert_eq!(desc.name, "epics-tools:test:chars");
+    assert_eq!(desc.field_type, FieldId::Char);
+    assert_eq!(desc.element_count, 16);
+    assert_eq!(desc.state, ConnectionState::Connected);
+    assert!(!desc.host.is_empty());
+    assert!(desc.read_access);
+}